@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use dilligent::PickleReader;
+
+fuzz_target!(|data: &[u8]| {
+    for maybe_op in PickleReader::new(data) {
+        if maybe_op.is_err() {
+            break;
+        }
+    }
+});
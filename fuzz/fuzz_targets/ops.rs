@@ -0,0 +1,163 @@
+#![no_main]
+
+//! Structure-aware target: skips the decoder and drives the interpreter with
+//! well-formed op sequences, so the fuzzer spends its time on stack, memo and
+//! mark handling instead of on producing valid encodings.
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use num_bigint::BigInt;
+
+use dilligent::{Interpreter, Op};
+
+#[derive(Arbitrary, Debug)]
+enum FuzzOp {
+    Proto(u8),
+    Appends,
+    Append,
+    EmptyDict,
+    Dict,
+    EmptyList,
+    List,
+    EmptySet,
+    AddItems,
+    FrozenSet,
+    Mark,
+    Pop,
+    PopMark,
+    Dup,
+    BInput(u8),
+    LongBInput(u32),
+    Memoize,
+    Binunicode(String),
+    ShortBinunicode(String),
+    Binunicode8(String),
+    String(Vec<u8>),
+    Unicode(String),
+    BinString(Vec<u8>),
+    ShortBinString(Vec<u8>),
+    BinBytes(Vec<u8>),
+    ShortBinBytes(Vec<u8>),
+    BinBytes8(Vec<u8>),
+    ByteArray8(Vec<u8>),
+    NextBuffer,
+    ReadonlyBuffer,
+    Global(String, String),
+    StackGlobal,
+    Ext1(u8),
+    Ext2(u16),
+    Ext4(u32),
+    None,
+    Int(i64),
+    Long(Vec<u8>),
+    BinInt(i32),
+    BinInt1(u8),
+    BinInt2(u16),
+    Long1(Vec<u8>),
+    Long4(Vec<u8>),
+    Float(f64),
+    BinFloat(f64),
+    Get(u32),
+    Put(u32),
+    BinGet(u8),
+    PersId(String),
+    BinPersId,
+    LongBinGet(u32),
+    Tuple,
+    TupleN(u8),
+    True,
+    False,
+    Reduce,
+    NewObj,
+    NewObjEx,
+    Inst(String, String),
+    Obj,
+    SetItems,
+    SetItem,
+    Build,
+    Frame(u64),
+    Stop,
+}
+
+impl From<FuzzOp> for Op {
+    fn from(op: FuzzOp) -> Self {
+        match op {
+            FuzzOp::Proto(v) => Op::Proto(v),
+            FuzzOp::Appends => Op::Appends,
+            FuzzOp::Append => Op::Append,
+            FuzzOp::EmptyDict => Op::EmptyDict,
+            FuzzOp::Dict => Op::Dict,
+            FuzzOp::EmptyList => Op::EmptyList,
+            FuzzOp::List => Op::List,
+            FuzzOp::EmptySet => Op::EmptySet,
+            FuzzOp::AddItems => Op::AddItems,
+            FuzzOp::FrozenSet => Op::FrozenSet,
+            FuzzOp::Mark => Op::Mark,
+            FuzzOp::Pop => Op::Pop,
+            FuzzOp::PopMark => Op::PopMark,
+            FuzzOp::Dup => Op::Dup,
+            FuzzOp::BInput(i) => Op::BInput(i),
+            FuzzOp::LongBInput(i) => Op::LongBInput(i),
+            FuzzOp::Memoize => Op::Memoize,
+            FuzzOp::Binunicode(s) => Op::Binunicode(s),
+            FuzzOp::ShortBinunicode(s) => Op::ShortBinunicode(s),
+            FuzzOp::Binunicode8(s) => Op::Binunicode8(s),
+            FuzzOp::String(b) => Op::String(b),
+            FuzzOp::Unicode(s) => Op::Unicode(s),
+            FuzzOp::BinString(b) => Op::BinString(b),
+            FuzzOp::ShortBinString(b) => Op::ShortBinString(b),
+            FuzzOp::BinBytes(b) => Op::BinBytes(b),
+            FuzzOp::ShortBinBytes(b) => Op::ShortBinBytes(b),
+            FuzzOp::BinBytes8(b) => Op::BinBytes8(b),
+            FuzzOp::ByteArray8(b) => Op::ByteArray8(b),
+            FuzzOp::NextBuffer => Op::NextBuffer,
+            FuzzOp::ReadonlyBuffer => Op::ReadonlyBuffer,
+            FuzzOp::Global(m, n) => Op::Global(m, n),
+            FuzzOp::StackGlobal => Op::StackGlobal,
+            FuzzOp::Ext1(c) => Op::Ext1(c),
+            FuzzOp::Ext2(c) => Op::Ext2(c),
+            FuzzOp::Ext4(c) => Op::Ext4(c),
+            FuzzOp::None => Op::None,
+            FuzzOp::Int(v) => Op::Int(v),
+            FuzzOp::Long(b) => Op::Long(BigInt::from_signed_bytes_le(&b)),
+            FuzzOp::BinInt(v) => Op::BinInt(v),
+            FuzzOp::BinInt1(v) => Op::BinInt1(v),
+            FuzzOp::BinInt2(v) => Op::BinInt2(v),
+            FuzzOp::Long1(b) => Op::Long1(BigInt::from_signed_bytes_le(&b)),
+            FuzzOp::Long4(b) => Op::Long4(BigInt::from_signed_bytes_le(&b)),
+            FuzzOp::Float(v) => Op::Float(v),
+            FuzzOp::BinFloat(v) => Op::BinFloat(v),
+            FuzzOp::Get(i) => Op::Get(i),
+            FuzzOp::Put(i) => Op::Put(i),
+            FuzzOp::BinGet(i) => Op::BinGet(i),
+            FuzzOp::PersId(s) => Op::PersId(s),
+            FuzzOp::BinPersId => Op::BinPersId,
+            FuzzOp::LongBinGet(i) => Op::LongBinGet(i),
+            FuzzOp::Tuple => Op::Tuple,
+            FuzzOp::TupleN(n) => Op::TupleN(n),
+            FuzzOp::True => Op::True,
+            FuzzOp::False => Op::False,
+            FuzzOp::Reduce => Op::Reduce,
+            FuzzOp::NewObj => Op::NewObj,
+            FuzzOp::NewObjEx => Op::NewObjEx,
+            FuzzOp::Inst(m, n) => Op::Inst(m, n),
+            FuzzOp::Obj => Op::Obj,
+            FuzzOp::SetItems => Op::SetItems,
+            FuzzOp::SetItem => Op::SetItem,
+            FuzzOp::Build => Op::Build,
+            FuzzOp::Frame(len) => Op::Frame(len),
+            FuzzOp::Stop => Op::Stop,
+        }
+    }
+}
+
+fuzz_target!(|ops: Vec<FuzzOp>| {
+    let mut interp = Interpreter::new();
+
+    for op in ops {
+        match interp.exec_op(op.into()) {
+            Ok(false) => {}
+            Ok(true) | Err(_) => break,
+        }
+    }
+});
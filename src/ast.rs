@@ -1,18 +1,57 @@
-#[derive(Debug)]
+use num_bigint::BigInt;
+
+/// A single decoded pickle opcode together with its arguments.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Op {
     Proto(u8),
     Appends,
+    Append,
     EmptyDict,
+    Dict,
     EmptyList,
+    List,
+    EmptySet,
+    AddItems,
+    FrozenSet,
     Mark,
+    Pop,
+    PopMark,
+    Dup,
     BInput(u8),
     LongBInput(u32),
+    Memoize,
     Binunicode(String),
+    ShortBinunicode(String),
+    Binunicode8(String),
+    String(Vec<u8>),
+    Unicode(String),
+    BinString(Vec<u8>),
+    ShortBinString(Vec<u8>),
+    BinBytes(Vec<u8>),
+    ShortBinBytes(Vec<u8>),
+    BinBytes8(Vec<u8>),
+    ByteArray8(Vec<u8>),
+    NextBuffer,
+    ReadonlyBuffer,
     Global(String, String),
+    StackGlobal,
+    Ext1(u8),
+    Ext2(u16),
+    Ext4(u32),
+    None,
+    Int(i64),
+    Long(BigInt),
     BinInt(i32),
-    BinInt1(i8),
-    BinInt2(i16),
+    BinInt1(u8),
+    BinInt2(u16),
+    Long1(BigInt),
+    Long4(BigInt),
+    Float(f64),
+    BinFloat(f64),
+    Get(u32),
+    Put(u32),
     BinGet(u8),
+    PersId(String),
     BinPersId,
     LongBinGet(u32),
     Tuple,
@@ -20,8 +59,13 @@ pub enum Op {
     True,
     False,
     Reduce,
+    NewObj,
+    NewObjEx,
+    Inst(String, String),
+    Obj,
     SetItems,
     SetItem,
     Build,
+    Frame(u64),
     Stop
-}
\ No newline at end of file
+}
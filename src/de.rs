@@ -0,0 +1,200 @@
+use serde::de::value::{BorrowedStrDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeSeed, EnumAccess, IntoDeserializer, VariantAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use num_bigint::BigInt;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::interpreter::Value;
+
+/// Deserializes a `T` from a depickled `value`, borrowing strings and bytes
+/// from it.
+///
+/// Dicts and OrderedDicts deserialize as maps or structs, tuples, lists and
+/// sets as sequences and `None` as unit or an absent option. Enums are read
+/// from a variant name or a dict holding a single `{variant: value}` entry.
+/// Globals, calls and torch objects fail with [`ErrorKind::Unsupported`]
+/// unless they are ignored.
+pub fn from_value<'de, T: de::Deserialize<'de>>(value: &'de Value) -> Result<T> {
+    T::deserialize(value)
+}
+
+impl<'de> de::Deserializer<'de> for &'de Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Value::None => visitor.visit_unit(),
+            Value::Int(value) => visitor.visit_i64(*value),
+            Value::BigInt(value) => visit_bigint(value, visitor),
+            Value::Float(value) => visitor.visit_f64(*value),
+            Value::String(value) => visitor.visit_borrowed_str(value),
+            Value::Bytes(data) | Value::ByteArray(data) => visitor.visit_borrowed_bytes(data),
+            Value::Bool(value) => visitor.visit_bool(*value),
+            Value::Dict(dict) => {
+                let mut map = MapDeserializer::<_, Error>::new(dict.iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            Value::OrderedDict(dict) => {
+                let mut map = MapDeserializer::<_, Error>::new(dict.iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            Value::Tuple(items) | Value::List(items) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(items.iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Set(set) | Value::FrozenSet(set) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(set.iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            _ => Err(unsupported(self)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Value::None => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            // Lets bytes fill a `Vec<u8>`.
+            Value::Bytes(data) | Value::ByteArray(data) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(data.iter().copied());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let entry = match self {
+            Value::String(variant) => return visitor.visit_enum(BorrowedStrDeserializer::new(variant)),
+            Value::Dict(dict) if dict.len() == 1 => dict.iter().next(),
+            Value::OrderedDict(dict) if dict.len() == 1 => dict.iter().next(),
+            _ => None,
+        };
+
+        match entry {
+            Some((variant, value)) => visitor.visit_enum(Variant { variant, value }),
+            None => Err(ErrorKind::Deserialize(format!(
+                "expected a variant name or a single entry dict for enum {}",
+                name
+            ))
+            .into()),
+        }
+    }
+
+    // Skipped fields may hold anything, including values that would not
+    // deserialize.
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn visit_bigint<'de, V: Visitor<'de>>(value: &BigInt, visitor: V) -> Result<V::Value> {
+    if let Ok(value) = i64::try_from(value) {
+        visitor.visit_i64(value)
+    }
+    else if let Ok(value) = u64::try_from(value) {
+        visitor.visit_u64(value)
+    }
+    else if let Ok(value) = i128::try_from(value) {
+        visitor.visit_i128(value)
+    }
+    else if let Ok(value) = u128::try_from(value) {
+        visitor.visit_u128(value)
+    }
+    else {
+        Err(ErrorKind::Deserialize(format!("integer {} does not fit in 128 bits", value)).into())
+    }
+}
+
+fn unsupported(value: &Value) -> Error {
+    let what = match value {
+        Value::Global(global) => format!("global {}", global),
+        Value::Function(function) => format!("function {}", function.name()),
+        Value::Reduce(func, _) => match &**func {
+            Value::Global(global) => format!("a call to {}", global),
+            Value::Function(function) => format!("a call to {}", function.name()),
+            _ => "a call".to_string(),
+        },
+        Value::SetState(..) => "an object built with BUILD".to_string(),
+        Value::PersistentLoad(_) => "a persistent id".to_string(),
+        Value::Storage(_) => "a torch storage".to_string(),
+        Value::Tensor(_) => "a tensor".to_string(),
+        _ => format!("{:?}", value),
+    };
+
+    ErrorKind::Unsupported(format!("can not deserialize {}", what)).into()
+}
+
+/// An enum variant stored as a dict's only entry.
+struct Variant<'de> {
+    variant: &'de Value,
+    value: &'de Value,
+}
+
+impl<'de> EnumAccess<'de> for Variant<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self)> {
+        let variant = seed.deserialize(self.variant)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Variant<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self.value)
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value> {
+        seed.deserialize(self.value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self.value, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self.value, visitor)
+    }
+}
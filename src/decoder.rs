@@ -1,9 +1,9 @@
-use std::io::{self, BufRead, BufReader};
-use std::path::PathBuf;
-use byteorder::{LittleEndian, ReadBytesExt};
-use eyre::{eyre, Result};
+use std::io::{self, BufRead, Read};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use num_bigint::BigInt;
 
 use crate::ast::Op;
+use crate::error::{Error, ErrorKind, Limit, Result};
 use crate::opcodes::OpCode;
 
 
@@ -13,46 +13,352 @@ fn read_byte<R: io::Read>(mut r: R) -> io::Result<Option<u8>> {
     r.read(&mut buffer).map(|count| match count { 1 => Some(buffer[0]), _ => None})
 }
 
-fn read_bytes<R: io::Read>(mut r: R, amount: usize) -> io::Result<Vec<u8>> {
-    let mut buf = vec![0; amount];
-    r.read_exact(&mut buf)?;
+/// Grows the buffer as data arrives, so a bogus length can not force a huge
+/// allocation before the input runs out.
+fn read_bytes<R: io::Read>(r: R, amount: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(amount as u64).read_to_end(&mut buf)?;
+
+    if buf.len() < amount {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
     Ok(buf)
 }
 
-fn read_line_string<R: io::BufRead>(mut r: R) -> Result<String> {
-    let mut buf = String::new();
-    r.read_line(&mut buf)?;
+fn read_len8<R: io::Read>(mut r: R) -> Result<usize> {
+    let len = r.read_u64::<LittleEndian>()?;
+    usize::try_from(len).map_err(|_| invalid_argument(format!("length {len} does not fit in memory")))
+}
+
+fn read_line_bytes<R: io::BufRead>(r: R, max_len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(max_len as u64 + 1).read_until(b'\n', &mut buf)?;
 
-    if !buf.ends_with('\n') {
-        return Err(eyre!("End of file encountered before end of line"));
+    if buf.pop() != Some(b'\n') {
+        if buf.len() >= max_len {
+            return Err(Limit::PayloadLen.exceeded(max_len));
+        }
+        return Err(ErrorKind::Truncated.into());
     }
-    buf.truncate(buf.len()-1);
     Ok(buf)
 }
 
+fn invalid_argument(message: impl Into<String>) -> Error {
+    ErrorKind::InvalidArgument(message.into()).into()
+}
+
+/// Parses an INT literal. Python 2 wrote ints too big for a machine word as
+/// INT too, so those become a LONG.
+fn parse_int(s: &str) -> Result<Op> {
+    let digits = s.trim();
+    match digits.parse::<i64>() {
+        Ok(value) => Ok(Op::Int(value)),
+        Err(_) => digits
+            .parse::<BigInt>()
+            .map(Op::Long)
+            .map_err(|_| invalid_argument(format!("invalid integer literal {s:?}"))),
+    }
+}
+
+fn parse_memo_key(s: &str) -> Result<u32> {
+    s.parse::<u32>().map_err(|_| invalid_argument(format!("invalid memo key {s:?}")))
+}
+
+/// Decodes the repr-quoted payload of a protocol 0 STRING op, mirroring
+/// python's `codecs.escape_decode`.
+fn unquote_string(line: &[u8]) -> Result<Vec<u8>> {
+    let body = match line {
+        [q @ (b'\'' | b'"'), body @ .., end] if end == q => body,
+        _ => return Err(invalid_argument("the STRING opcode argument must be quoted")),
+    };
+
+    let mut out = Vec::with_capacity(body.len());
+    let mut chars = body.iter().copied().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != b'\\' {
+            out.push(c);
+            continue;
+        }
+
+        let escaped = chars.next().ok_or_else(|| invalid_argument("trailing \\ in STRING"))?;
+        match escaped {
+            b'\n' => {}
+            b'\\' | b'\'' | b'"' => out.push(escaped),
+            b'a' => out.push(0x07),
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'v' => out.push(0x0b),
+            b'0'..=b'7' => {
+                let mut value = u32::from(escaped - b'0');
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(&d @ b'0'..=b'7') => {
+                            value = value * 8 + u32::from(d - b'0');
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                out.push(value as u8);
+            }
+            b'x' => {
+                let hex = [
+                    chars.next().ok_or_else(|| invalid_argument("truncated \\x escape in STRING"))?,
+                    chars.next().ok_or_else(|| invalid_argument("truncated \\x escape in STRING"))?,
+                ];
+                out.push(parse_hex(&hex)? as u8);
+            }
+            other => {
+                out.push(b'\\');
+                out.push(other);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Decodes the payload of a protocol 0 UNICODE op, mirroring python's
+/// `raw-unicode-escape` codec: only `\uXXXX` and `\UXXXXXXXX` after an odd
+/// number of backslashes are escapes and every other byte is a latin-1 code
+/// point.
+fn decode_raw_unicode_escape(line: &[u8]) -> Result<String> {
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+    // Backslashes right before `i`, so `\\u` is not an escape.
+    let mut backslashes = 0;
+
+    while i < line.len() {
+        let c = line[i];
+        let digits = match line.get(i + 1) {
+            Some(b'u') if c == b'\\' && backslashes % 2 == 0 => 4,
+            Some(b'U') if c == b'\\' && backslashes % 2 == 0 => 8,
+            _ => 0,
+        };
+
+        if digits == 0 {
+            backslashes = if c == b'\\' { backslashes + 1 } else { 0 };
+            out.push(char::from(c));
+            i += 1;
+            continue;
+        }
+        backslashes = 0;
+
+        let hex = line
+            .get(i + 2..i + 2 + digits)
+            .ok_or_else(|| invalid_argument("truncated \\u escape in UNICODE"))?;
+        let code_point = parse_hex(hex)?;
+        out.push(char::from_u32(code_point).ok_or_else(|| invalid_argument(format!("invalid code point {code_point:#x} in UNICODE")))?);
+        i += 2 + digits;
+    }
+
+    Ok(out)
+}
+
+fn parse_hex(digits: &[u8]) -> Result<u32> {
+    // `from_str_radix` would also take a sign.
+    std::str::from_utf8(digits)
+        .ok()
+        .filter(|s| s.bytes().all(|d| d.is_ascii_hexdigit()))
+        .and_then(|s| u32::from_str_radix(s, 16).ok())
+        .ok_or_else(|| invalid_argument(format!("invalid hex escape {:?}", String::from_utf8_lossy(digits))))
+}
+
+/// Reads from the current protocol 4 frame while one is active and from the
+/// underlying file otherwise.
+struct Unframer<R: BufRead> {
+    inner: R,
+    frame: Option<io::Cursor<Vec<u8>>>,
+    position: u64,
+}
+
+impl <R: BufRead> Unframer<R> {
+    fn in_frame(&self) -> bool {
+        self.frame.is_some()
+    }
+
+    fn frame_remaining(&self) -> u64 {
+        self.frame
+            .as_ref()
+            .map(|frame| frame.get_ref().len() as u64 - frame.position())
+            .unwrap_or(0)
+    }
+
+    /// Drops the current frame once every byte of it has been consumed.
+    fn end_exhausted_frame(&mut self) {
+        if self.in_frame() && self.frame_remaining() == 0 {
+            self.frame = None;
+        }
+    }
+
+    fn start_frame(&mut self, len: u64) -> Result<()> {
+        if self.frame_remaining() > 0 {
+            return Err(ErrorKind::InvalidFrame(format!(
+                "new frame started with {} bytes left in the current frame",
+                self.frame_remaining()
+            )).into());
+        }
+
+        let mut data = Vec::new();
+        (&mut self.inner).take(len).read_to_end(&mut data)?;
+
+        if (data.len() as u64) < len {
+            return Err(ErrorKind::InvalidFrame(format!(
+                "frame length {len} exceeds the remaining input of {} bytes",
+                data.len()
+            )).into());
+        }
+
+        self.frame = Some(io::Cursor::new(data));
+        Ok(())
+    }
+}
+
+impl <R: BufRead> io::Read for Unframer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = match &mut self.frame {
+            Some(frame) => frame.read(buf)?,
+            None => self.inner.read(buf)?,
+        };
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl <R: BufRead> BufRead for Unframer<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match &mut self.frame {
+            Some(frame) => frame.fill_buf(),
+            None => self.inner.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+        match &mut self.frame {
+            Some(frame) => frame.consume(amt),
+            None => self.inner.consume(amt),
+        }
+    }
+}
+
+/// Bounds on how much memory decoding a single pickle may use.
+#[derive(Debug, Clone)]
+pub struct DecoderLimits {
+    /// Longest string, bytes, long or text line a single op may carry.
+    pub max_payload_len: usize,
+    /// Largest protocol 4 frame that will be buffered.
+    pub max_frame_len: u64,
+    /// Total bytes all payloads and frames may allocate.
+    pub max_alloc_bytes: u64,
+}
+
+impl Default for DecoderLimits {
+    fn default() -> Self {
+        DecoderLimits {
+            max_payload_len: 512 << 20,
+            max_frame_len: 512 << 20,
+            max_alloc_bytes: 2 << 30,
+        }
+    }
+}
+
+/// Decodes a pickle byte stream into [`Op`]s, one per iteration.
 pub struct PickleReader<R: BufRead> {
-    pickle_file: R
+    pickle_file: Unframer<R>,
+    limits: DecoderLimits,
+    allocated: u64,
+    op_offset: u64,
+    op_code: Option<OpCode>,
 }
 
 impl <R: BufRead> PickleReader<R> {
     pub fn new(pickle_file: R) -> Self {
-        PickleReader { pickle_file }
+        Self::with_limits(pickle_file, DecoderLimits::default())
+    }
+
+    pub fn with_limits(pickle_file: R, limits: DecoderLimits) -> Self {
+        PickleReader {
+            pickle_file: Unframer { inner: pickle_file, frame: None, position: 0 },
+            limits,
+            allocated: 0,
+            op_offset: 0,
+            op_code: None,
+        }
+    }
+
+    /// Byte offset of the start of the most recently read op.
+    pub fn op_offset(&self) -> u64 {
+        self.op_offset
+    }
+
+    /// Opcode of the most recently read op, if it was a known one.
+    pub fn op_code(&self) -> Option<OpCode> {
+        self.op_code
+    }
+
+    /// Number of bytes consumed from the underlying stream so far.
+    pub fn position(&self) -> u64 {
+        self.pickle_file.position
+    }
+
+    /// Prepares to read the next of several pickles written back to back.
+    /// Whatever is left of the current frame after STOP is skipped, and the
+    /// allocation budget starts over.
+    pub fn next_pickle(&mut self) {
+        self.pickle_file.position += self.pickle_file.frame_remaining();
+        self.pickle_file.frame = None;
+        self.allocated = 0;
+        self.op_code = None;
+    }
+
+    /// Whether the underlying stream has been read to the end.
+    pub fn at_eof(&mut self) -> Result<bool> {
+        Ok(self.pickle_file.fill_buf()?.is_empty())
+    }
+
+    /// Accounts for a payload of `len` bytes against the limits.
+    fn charge(&mut self, len: usize) -> Result<()> {
+        if len > self.limits.max_payload_len {
+            return Err(Limit::PayloadLen.exceeded(self.limits.max_payload_len));
+        }
+
+        self.allocated = self.allocated.saturating_add(len as u64);
+        if self.allocated > self.limits.max_alloc_bytes {
+            return Err(Limit::AllocBytes.exceeded(self.limits.max_alloc_bytes));
+        }
+        Ok(())
+    }
+
+    fn read_payload(&mut self, len: usize) -> Result<Vec<u8>> {
+        self.charge(len)?;
+        Ok(read_bytes(&mut self.pickle_file, len)?)
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>> {
+        let line = read_line_bytes(&mut self.pickle_file, self.limits.max_payload_len)?;
+        self.charge(line.len())?;
+        Ok(line)
+    }
+
+    fn read_line_string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.read_line()?)?)
     }
 
     fn try_pase_op(&mut self, op_code: OpCode) -> Result<Op> {
         let maybe_parsed_op = match op_code {
             OpCode::Proto => {
-                let version = read_byte(&mut self.pickle_file)?.ok_or(
-                    eyre!("Protocol version expected")
-                )?;
+                let version = read_byte(&mut self.pickle_file)?.ok_or(ErrorKind::Truncated)?;
 
                 Op::Proto(version)
             },
             OpCode::EmptyDict => Op::EmptyDict,
             OpCode::Binput => {
-                let val = read_byte(&mut self.pickle_file)?.ok_or(
-                    eyre!("Byte value expected")
-                )?;
+                let val = read_byte(&mut self.pickle_file)?.ok_or(ErrorKind::Truncated)?;
 
                 Op::BInput(val)
             },
@@ -62,13 +368,13 @@ impl <R: BufRead> PickleReader<R> {
             },
             OpCode::Binunicode => {
                 let len = self.pickle_file.read_u32::<LittleEndian>()?;
-                let data = read_bytes(&mut self.pickle_file, len as usize)?;
+                let data = self.read_payload(len as usize)?;
                 let s: String = String::from_utf8(data)?;
                 Op::Binunicode(s)
             },
             OpCode::Global => {
-                let module = read_line_string(&mut self.pickle_file)?;
-                let name = read_line_string(&mut self.pickle_file)?;
+                let module = self.read_line_string()?;
+                let name = self.read_line_string()?;
 
                 Op::Global(module, name)
             },
@@ -77,11 +383,11 @@ impl <R: BufRead> PickleReader<R> {
                 Op::BinInt(value)
             },
             OpCode::Binint1 => {
-                let value = self.pickle_file.read_i8()?;
+                let value = self.pickle_file.read_u8()?;
                 Op::BinInt1(value)
             },
             OpCode::Binint2 => {
-                let value = self.pickle_file.read_i16::<LittleEndian>()?;
+                let value = self.pickle_file.read_u16::<LittleEndian>()?;
                 Op::BinInt2(value)
             },
             OpCode::Binget => {
@@ -106,63 +412,188 @@ impl <R: BufRead> PickleReader<R> {
             OpCode::Setitems => Op::SetItems,
             OpCode::Appends => Op::Appends,
             OpCode::Stop => Op::Stop,
-            OpCode::Pop => todo!(),
-            OpCode::PopMark => todo!(),
-            OpCode::Dup => todo!(),
-            OpCode::Float => todo!(),
-            OpCode::Int => todo!(),
-            OpCode::Long => todo!(),
-            OpCode::None => todo!(),
-            OpCode::Persid => todo!(),
-            OpCode::String => todo!(),
-            OpCode::Binstring => todo!(),
-            OpCode::ShortBinstring => todo!(),
-            OpCode::Unicode => todo!(),
-            OpCode::Append => todo!(),
+            OpCode::Pop => Op::Pop,
+            OpCode::PopMark => Op::PopMark,
+            OpCode::Dup => Op::Dup,
+            OpCode::Float => {
+                let line = self.read_line_string()?;
+                let value = line.parse::<f64>().map_err(|_| invalid_argument(format!("invalid FLOAT value {line:?}")))?;
+                Op::Float(value)
+            },
+            OpCode::Int => {
+                let line = self.read_line_string()?;
+                match line.as_str() {
+                    "00" => Op::False,
+                    "01" => Op::True,
+                    _ => parse_int(&line)?,
+                }
+            },
+            OpCode::Long => {
+                let line = self.read_line_string()?;
+                let digits = line.strip_suffix('L').unwrap_or(&line);
+                let value = digits
+                    .parse::<BigInt>()
+                    .map_err(|_| invalid_argument(format!("invalid LONG literal {line:?}")))?;
+                Op::Long(value)
+            },
+            OpCode::String => {
+                let line = self.read_line()?;
+                Op::String(unquote_string(&line)?)
+            },
+            OpCode::Unicode => {
+                let line = self.read_line()?;
+                Op::Unicode(decode_raw_unicode_escape(&line)?)
+            },
+            OpCode::Get => {
+                let line = self.read_line_string()?;
+                Op::Get(parse_memo_key(&line)?)
+            },
+            OpCode::Put => {
+                let line = self.read_line_string()?;
+                Op::Put(parse_memo_key(&line)?)
+            },
+            OpCode::Inst => {
+                let module = self.read_line_string()?;
+                let name = self.read_line_string()?;
+
+                Op::Inst(module, name)
+            },
+            OpCode::None => Op::None,
+            OpCode::Persid => {
+                let pid = self.read_line_string()?;
+                Op::PersId(pid)
+            },
+            OpCode::Binstring => {
+                let len = self.pickle_file.read_i32::<LittleEndian>()?;
+                let len = usize::try_from(len).map_err(|_| invalid_argument("BINSTRING pickle has negative byte count"))?;
+                Op::BinString(self.read_payload(len)?)
+            },
+            OpCode::ShortBinstring => {
+                let len = self.pickle_file.read_u8()?;
+                Op::ShortBinString(self.read_payload(len as usize)?)
+            },
+            OpCode::Append => Op::Append,
             OpCode::Build => Op::Build,
-            OpCode::Dict => todo!(),
-            OpCode::Get => todo!(),
-            OpCode::Inst => todo!(),
-            OpCode::List => todo!(),
-            OpCode::Obj => todo!(),
-            OpCode::Put => todo!(),
+            OpCode::Dict => Op::Dict,
+            OpCode::List => Op::List,
+            OpCode::Obj => Op::Obj,
             OpCode::Setitem => Op::SetItem,
-            OpCode::Binfloat => todo!(),
-            OpCode::Newobj => todo!(),
-            OpCode::Ext1 => todo!(),
-            OpCode::Ext2 => todo!(),
-            OpCode::Ext4 => todo!(),
-            OpCode::Long1 => todo!(),
-            OpCode::Long4 => todo!(),
-            OpCode::Binbytes => todo!(),
-            OpCode::ShortBinbytes => todo!(),
-            OpCode::ShortBinunicode => todo!(),
-            OpCode::Binunicode8 => todo!(),
-            OpCode::Binbytes8 => todo!(),
-            OpCode::EmptySet => todo!(),
-            OpCode::Additems => todo!(),
-            OpCode::Frozenset => todo!(),
-            OpCode::NewobjEx => todo!(),
-            OpCode::StackGlobal => todo!(),
-            OpCode::Memoize => todo!(),
-            OpCode::Frame => todo!(),
-            OpCode::Bytearray8 => todo!(),
-            OpCode::NextBuffer => todo!(),
-            OpCode::ReadonlyBuffer => todo!(),
+            OpCode::Binfloat => {
+                let value = self.pickle_file.read_f64::<BigEndian>()?;
+                Op::BinFloat(value)
+            },
+            OpCode::Newobj => Op::NewObj,
+            OpCode::Ext1 => {
+                let code = self.pickle_file.read_u8()?;
+                Op::Ext1(code)
+            },
+            OpCode::Ext2 => {
+                let code = self.pickle_file.read_u16::<LittleEndian>()?;
+                Op::Ext2(code)
+            },
+            OpCode::Ext4 => {
+                let code = self.pickle_file.read_u32::<LittleEndian>()?;
+                Op::Ext4(code)
+            },
+            OpCode::Long1 => {
+                let len = self.pickle_file.read_u8()?;
+                let data = self.read_payload(len as usize)?;
+                Op::Long1(BigInt::from_signed_bytes_le(&data))
+            },
+            OpCode::Long4 => {
+                let len = self.pickle_file.read_i32::<LittleEndian>()?;
+                let len = usize::try_from(len).map_err(|_| invalid_argument("LONG pickle has negative byte count"))?;
+                let data = self.read_payload(len)?;
+                Op::Long4(BigInt::from_signed_bytes_le(&data))
+            },
+            OpCode::Binbytes => {
+                let len = self.pickle_file.read_u32::<LittleEndian>()?;
+                Op::BinBytes(self.read_payload(len as usize)?)
+            },
+            OpCode::ShortBinbytes => {
+                let len = self.pickle_file.read_u8()?;
+                Op::ShortBinBytes(self.read_payload(len as usize)?)
+            },
+            OpCode::ShortBinunicode => {
+                let len = self.pickle_file.read_u8()?;
+                let data = self.read_payload(len as usize)?;
+                Op::ShortBinunicode(String::from_utf8(data)?)
+            },
+            OpCode::Binunicode8 => {
+                let len = read_len8(&mut self.pickle_file)?;
+                let data = self.read_payload(len)?;
+                Op::Binunicode8(String::from_utf8(data)?)
+            },
+            OpCode::Binbytes8 => {
+                let len = read_len8(&mut self.pickle_file)?;
+                Op::BinBytes8(self.read_payload(len)?)
+            },
+            OpCode::EmptySet => Op::EmptySet,
+            OpCode::Additems => Op::AddItems,
+            OpCode::Frozenset => Op::FrozenSet,
+            OpCode::NewobjEx => Op::NewObjEx,
+            OpCode::StackGlobal => Op::StackGlobal,
+            OpCode::Memoize => Op::Memoize,
+            OpCode::Frame => {
+                let len = self.pickle_file.read_u64::<LittleEndian>()?;
+                Op::Frame(len)
+            },
+            OpCode::Bytearray8 => {
+                let len = read_len8(&mut self.pickle_file)?;
+                Op::ByteArray8(self.read_payload(len)?)
+            },
+            OpCode::NextBuffer => Op::NextBuffer,
+            OpCode::ReadonlyBuffer => Op::ReadonlyBuffer,
         };
         Ok(maybe_parsed_op)
     }
 
     fn get_next_op(&mut self) -> Result<Option<Op>> {
-        let maybe_op = read_byte(&mut self.pickle_file)?;
+        self.pickle_file.end_exhausted_frame();
+        let started_in_frame = self.pickle_file.in_frame();
+
+        self.op_offset = self.pickle_file.position;
+        self.op_code = None;
+
+        let parsed = self.parse_next_op();
+
+        let result = match parsed {
+            Err(err) if started_in_frame
+                && self.pickle_file.frame_remaining() == 0
+                && matches!(err.kind(), ErrorKind::Truncated) => {
+                Err(ErrorKind::InvalidFrame("opcode straddles the end of its frame".to_string()).into())
+            }
+            Ok(Some(Op::Frame(len))) => {
+                self.start_frame(len).map(|_| Some(Op::Frame(len)))
+            }
+            other => other,
+        };
 
-        maybe_op.map(|op| {
-            let op_code: OpCode = op.try_into()?;
-            self.try_pase_op(op_code)
-        }).transpose()
+        result.map_err(|err: Error| err.at(self.op_offset, self.op_code))
     }
 
+    fn start_frame(&mut self, len: u64) -> Result<()> {
+        if len > self.limits.max_frame_len {
+            return Err(Limit::FrameLen.exceeded(self.limits.max_frame_len));
+        }
+
+        self.allocated = self.allocated.saturating_add(len);
+        if self.allocated > self.limits.max_alloc_bytes {
+            return Err(Limit::AllocBytes.exceeded(self.limits.max_alloc_bytes));
+        }
+
+        self.pickle_file.start_frame(len)
+    }
 
+    fn parse_next_op(&mut self) -> Result<Option<Op>> {
+        let Some(op) = read_byte(&mut self.pickle_file)? else {
+            return Ok(None);
+        };
+
+        let op_code = OpCode::try_from(op).map_err(|_| ErrorKind::UnknownOpcode(op))?;
+        self.op_code = Some(op_code);
+        self.try_pase_op(op_code).map(Some)
+    }
 }
 
 impl <R: BufRead> Iterator for PickleReader<R> {
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+use crate::ast::Op;
+use crate::decoder::PickleReader;
+use crate::error::Result;
+use crate::interpreter::Interpreter;
+use crate::opcodes::OpCode;
+
+const INDENT: &str = "    ";
+
+/// Writes a listing of the next pickle from `reader` in the style of python's
+/// `pickletools.dis`, stopping after STOP.
+///
+/// Each line holds the op's byte offset, raw opcode, name and argument,
+/// indented by the number of open MARKs. Ops that close a MARK note where it
+/// was, and MEMOIZE notes the memo index it stores to. With `stack_depth` the
+/// ops are also run through an [`Interpreter`] and the depth of the stack
+/// after each one is appended, or `?` once the interpreter has given up.
+pub fn dis<R: BufRead, W: Write>(reader: &mut PickleReader<R>, out: &mut W, stack_depth: bool) -> Result<()> {
+    let mut interp = stack_depth.then(Interpreter::new);
+    let mut marks: Vec<u64> = Vec::new();
+    let mut memo = HashSet::new();
+    let mut protocol = 0;
+
+    while let Some(op) = reader.next().transpose()? {
+        let Some(opcode) = reader.op_code() else {
+            continue;
+        };
+        let offset = reader.op_offset();
+        protocol = protocol.max(opcode.protocol());
+
+        // Ops closing a MARK stay indented with the values they consume.
+        let indent = INDENT.repeat(marks.len());
+        let mut note = None;
+        if closes_mark(opcode) {
+            note = marks.pop().map(|pos| format!("(MARK at {})", pos));
+        }
+
+        let memo_index = match &op {
+            Op::BInput(index) => Some(*index as u32),
+            Op::LongBInput(index) | Op::Put(index) => Some(*index),
+            Op::Memoize => {
+                let index = memo.len() as u32;
+                note = Some(format!("(as {})", index));
+                Some(index)
+            }
+            _ => None,
+        };
+        if let Some(index) = memo_index {
+            memo.insert(index);
+        }
+
+        let mut line = format!(
+            "{:5}: {:<4} {}{}",
+            offset,
+            opcode_repr(opcode),
+            indent,
+            opcode.name()
+        );
+
+        let arg = argument(opcode, &op);
+        if arg.is_some() || note.is_some() {
+            line.push_str(&" ".repeat(10usize.saturating_sub(opcode.name().len())));
+        }
+        for part in arg.iter().chain(note.iter()) {
+            line.push(' ');
+            line.push_str(part);
+        }
+
+        if opcode == OpCode::Mark {
+            marks.push(offset);
+        }
+
+        if let Some(running) = interp.as_mut() {
+            if running.exec_op(op).is_err() {
+                interp = None;
+            }
+        }
+
+        if stack_depth {
+            let depth = interp.as_ref().map_or("?".to_string(), |interp| interp.stack_depth().to_string());
+            line = format!("{:<64} depth {}", line, depth);
+        }
+
+        writeln!(out, "{}", line)?;
+
+        if opcode == OpCode::Stop {
+            break;
+        }
+    }
+
+    writeln!(out, "highest protocol among opcodes = {}", protocol)?;
+
+    Ok(())
+}
+
+/// Whether the op pops everything back to the innermost MARK.
+fn closes_mark(opcode: OpCode) -> bool {
+    matches!(
+        opcode,
+        OpCode::PopMark
+            | OpCode::Tuple
+            | OpCode::List
+            | OpCode::Dict
+            | OpCode::Appends
+            | OpCode::Setitems
+            | OpCode::Additems
+            | OpCode::Frozenset
+            | OpCode::Inst
+            | OpCode::Obj
+    )
+}
+
+/// The opcode byte as python's `repr` shows it, without the quotes.
+fn opcode_repr(opcode: OpCode) -> String {
+    let byte = u8::from(opcode);
+    if byte.is_ascii_graphic() {
+        (byte as char).to_string()
+    }
+    else {
+        format!("\\x{:02x}", byte)
+    }
+}
+
+fn argument(opcode: OpCode, op: &Op) -> Option<String> {
+    let arg = match op {
+        Op::Proto(value) | Op::BInput(value) | Op::BinGet(value) | Op::BinInt1(value) | Op::Ext1(value) => {
+            value.to_string()
+        }
+        Op::BinInt2(value) | Op::Ext2(value) => value.to_string(),
+        Op::LongBInput(value) | Op::Get(value) | Op::Put(value) | Op::LongBinGet(value) | Op::Ext4(value) => {
+            value.to_string()
+        }
+        Op::Frame(len) => len.to_string(),
+        Op::Int(value) => value.to_string(),
+        Op::BinInt(value) => value.to_string(),
+        Op::Long(value) | Op::Long1(value) | Op::Long4(value) => value.to_string(),
+        Op::Float(value) | Op::BinFloat(value) => float_repr(*value),
+        // INT spells booleans as 01 and 00.
+        Op::True if opcode == OpCode::Int => "True".to_string(),
+        Op::False if opcode == OpCode::Int => "False".to_string(),
+        Op::Binunicode(s) | Op::ShortBinunicode(s) | Op::Binunicode8(s) | Op::Unicode(s) | Op::PersId(s) => {
+            str_repr(s)
+        }
+        // Python 2 strings are shown decoded as latin-1, like pickletools.
+        Op::String(data) | Op::BinString(data) | Op::ShortBinString(data) => {
+            str_repr(&data.iter().map(|&byte| byte as char).collect::<String>())
+        }
+        Op::BinBytes(data) | Op::ShortBinBytes(data) | Op::BinBytes8(data) | Op::ByteArray8(data) => {
+            bytes_repr(data)
+        }
+        Op::Global(module, name) | Op::Inst(module, name) => str_repr(&format!("{} {}", module, name)),
+        _ => return None,
+    };
+
+    Some(arg)
+}
+
+/// Rust's shortest round-trip formatting switches to an exponent where
+/// python does, but python always signs it and pads it to two digits.
+fn float_repr(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+
+    let repr = format!("{:?}", value);
+    match repr.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let (sign, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => ('-', digits),
+                None => ('+', exponent),
+            };
+            format!("{}e{}{:0>2}", mantissa, sign, digits)
+        }
+        None => repr,
+    }
+}
+
+/// Python quotes with `"` when that saves escaping a `'`.
+fn quote_for(has_single: bool, has_double: bool) -> char {
+    if has_single && !has_double { '"' } else { '\'' }
+}
+
+fn str_repr(s: &str) -> String {
+    let quote = quote_for(s.contains('\''), s.contains('"'));
+    let mut repr = String::with_capacity(s.len() + 2);
+    repr.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => repr.push_str("\\\\"),
+            c if c == quote => {
+                repr.push('\\');
+                repr.push(c);
+            }
+            '\n' => repr.push_str("\\n"),
+            '\r' => repr.push_str("\\r"),
+            '\t' => repr.push_str("\\t"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                let _ = write!(repr, "\\x{:02x}", c as u32);
+            }
+            c => repr.push(c),
+        }
+    }
+    repr.push(quote);
+    repr
+}
+
+fn bytes_repr(data: &[u8]) -> String {
+    let quote = quote_for(data.contains(&b'\''), data.contains(&b'"'));
+    let mut repr = String::with_capacity(data.len() + 3);
+    repr.push('b');
+    repr.push(quote);
+    for &byte in data {
+        match byte {
+            b'\\' => repr.push_str("\\\\"),
+            byte if byte as char == quote => {
+                repr.push('\\');
+                repr.push(quote);
+            }
+            b'\n' => repr.push_str("\\n"),
+            b'\r' => repr.push_str("\\r"),
+            b'\t' => repr.push_str("\\t"),
+            b' '..=b'~' => repr.push(byte as char),
+            _ => {
+                let _ = write!(repr, "\\x{:02x}", byte);
+            }
+        }
+    }
+    repr.push(quote);
+    repr
+}
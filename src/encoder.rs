@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::mem;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use itertools::Itertools;
+use num_bigint::BigInt;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::interpreter::{Global, Set, Value};
+use crate::opcodes::OpCode;
+use crate::tensor::{Storage, Tensor};
+
+/// Frames are cut once they reach this size, as python does.
+const FRAME_SIZE_TARGET: usize = 64 * 1024;
+
+/// Frames smaller than this are not worth their 9 byte header.
+const FRAME_SIZE_MIN: usize = 4;
+
+/// Items per APPENDS, SETITEMS or ADDITEMS.
+const BATCH_SIZE: usize = 1000;
+
+/// Modules renamed in python 3, which python writes and reads under their
+/// python 2 names below protocol 3.
+const PY2_MODULES: &[(&str, &str)] = &[("builtins", "__builtin__"), ("copyreg", "copy_reg")];
+
+/// Values that are written once and fetched from the memo after that. Only
+/// immutable values are shared, as a pickle can not say two equal values
+/// were distinct objects. Containers are never memoized: a [`Value`] owns its
+/// items, so it can not say which of them a pickle shared either.
+#[derive(PartialEq, Eq, Hash)]
+enum MemoKey {
+    String(String),
+    Bytes(Vec<u8>),
+    Global(Global),
+}
+
+/// What is left to write of a value, kept on a stack of its own rather than
+/// the call stack so deeply nested values can be written.
+enum Task<'a> {
+    Save(&'a Value),
+    Op(OpCode),
+}
+
+/// Encodes [`Value`]s as pickles, the inverse of [`PickleReader`] and
+/// [`Interpreter`].
+///
+/// Strings, bytes and globals that repeat within a pickle are memoized, and
+/// from protocol 4 the output is cut into frames. Lists, dicts and other
+/// containers are written out in full wherever they appear, so a container
+/// the original pickle shared is written once per reference. Torch storages and tensors
+/// are written the way `torch.save` writes them, as persistent ids and
+/// `torch._utils._rebuild_tensor_v2` calls.
+///
+/// [`PickleReader`]: crate::PickleReader
+/// [`Interpreter`]: crate::Interpreter
+pub struct PickleWriter<W: Write> {
+    pickle_file: W,
+    protocol: u8,
+    /// Ops not yet written out, the current frame from protocol 4.
+    buffer: Vec<u8>,
+    memo: HashMap<MemoKey, u32>,
+}
+
+impl <W: Write> PickleWriter<W> {
+    /// A writer for `protocol` 2 to 5.
+    pub fn new(pickle_file: W, protocol: u8) -> Result<Self> {
+        if !(2..=5).contains(&protocol) {
+            return Err(ErrorKind::Unsupported(format!("writing pickle protocol {}", protocol)).into());
+        }
+
+        Ok(PickleWriter {
+            pickle_file,
+            protocol,
+            buffer: Vec::new(),
+            memo: HashMap::new(),
+        })
+    }
+
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Writes `value` as one complete pickle. Several pickles can be written
+    /// back to back, each with its own memo.
+    pub fn dump(&mut self, value: &Value) -> Result<()> {
+        self.memo.clear();
+
+        self.pickle_file.write_all(&[OpCode::Proto.into(), self.protocol])?;
+        self.save(value)?;
+        self.op(OpCode::Stop);
+        self.commit_frame(true)?;
+        self.pickle_file.flush()?;
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.pickle_file
+    }
+
+    fn op(&mut self, opcode: OpCode) {
+        self.buffer.push(opcode.into());
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Writes out the buffered ops, as a frame from protocol 4, once there
+    /// are enough of them or when `force`d.
+    fn commit_frame(&mut self, force: bool) -> Result<()> {
+        if self.buffer.is_empty() || (!force && self.buffer.len() < FRAME_SIZE_TARGET) {
+            return Ok(());
+        }
+
+        let data = mem::take(&mut self.buffer);
+        if self.protocol >= 4 && data.len() >= FRAME_SIZE_MIN {
+            self.pickle_file.write_u8(OpCode::Frame.into())?;
+            self.pickle_file.write_u64::<LittleEndian>(data.len() as u64)?;
+        }
+        self.pickle_file.write_all(&data)?;
+
+        Ok(())
+    }
+
+    /// Writes an op carrying a payload. Payloads too big for a frame end the
+    /// current one and are written unframed, so the reader need not buffer
+    /// them twice.
+    fn write_payload(&mut self, header: &[u8], payload: &[u8]) -> Result<()> {
+        if self.protocol >= 4 && payload.len() >= FRAME_SIZE_TARGET {
+            self.commit_frame(true)?;
+            self.pickle_file.write_all(header)?;
+            self.pickle_file.write_all(payload)?;
+        }
+        else {
+            self.write(header);
+            self.write(payload);
+        }
+
+        Ok(())
+    }
+
+    fn memoize(&mut self, key: MemoKey) {
+        let index = self.memo.len() as u32;
+        self.memo.insert(key, index);
+
+        if self.protocol >= 4 {
+            self.op(OpCode::Memoize);
+        }
+        else if let Ok(index) = u8::try_from(index) {
+            self.write(&[OpCode::Binput.into(), index]);
+        }
+        else {
+            self.op(OpCode::LongBinput);
+            self.write(&index.to_le_bytes());
+        }
+    }
+
+    /// Fetches a memoized value, returning whether there was one.
+    fn get(&mut self, key: &MemoKey) -> bool {
+        let Some(&index) = self.memo.get(key) else {
+            return false;
+        };
+
+        if let Ok(index) = u8::try_from(index) {
+            self.write(&[OpCode::Binget.into(), index]);
+        }
+        else {
+            self.op(OpCode::LongBinget);
+            self.write(&index.to_le_bytes());
+        }
+        true
+    }
+
+    fn save(&mut self, value: &Value) -> Result<()> {
+        let mut pending = vec![Task::Save(value)];
+        let mut next = Vec::new();
+
+        while let Some(task) = pending.pop() {
+            match task {
+                Task::Save(value) => {
+                    self.save_value(value, &mut next)?;
+                    pending.extend(next.drain(..).rev());
+                }
+                Task::Op(opcode) => self.op(opcode),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a scalar, or the start of a container and what is left of it
+    /// to `tasks` in the order it is to be written.
+    fn save_value<'a>(&mut self, value: &'a Value, tasks: &mut Vec<Task<'a>>) -> Result<()> {
+        self.commit_frame(false)?;
+
+        match value {
+            Value::None => self.op(OpCode::None),
+            Value::Bool(true) => self.op(OpCode::Newtrue),
+            Value::Bool(false) => self.op(OpCode::Newfalse),
+            Value::Int(value) => self.save_int(*value),
+            Value::BigInt(value) => self.save_long(value)?,
+            Value::Float(value) => {
+                self.op(OpCode::Binfloat);
+                self.write(&value.to_bits().to_be_bytes());
+            }
+            Value::String(s) => self.save_str(s)?,
+            Value::Bytes(data) => self.save_bytes(data)?,
+            Value::ByteArray(data) => self.save_bytearray(data)?,
+            Value::Tuple(items) => self.save_tuple(items, tasks),
+            Value::List(items) => {
+                self.op(OpCode::EmptyList);
+                batched(OpCode::Append, OpCode::Appends, items.iter().map(|item| [Task::Save(item)]), tasks);
+            }
+            Value::Dict(dict) => {
+                self.op(OpCode::EmptyDict);
+                batched(OpCode::Setitem, OpCode::Setitems, dict.iter().map(item_tasks), tasks);
+            }
+            Value::OrderedDict(dict) => {
+                self.save_global(&Global::new("collections", "OrderedDict"))?;
+                self.op(OpCode::EmptyTuple);
+                self.op(OpCode::Reduce);
+                batched(OpCode::Setitem, OpCode::Setitems, dict.iter().map(item_tasks), tasks);
+            }
+            Value::Set(set) => self.save_set(set, false, tasks)?,
+            Value::FrozenSet(set) => self.save_set(set, true, tasks)?,
+            Value::Global(global) => self.save_global(global)?,
+            Value::Function(function) => {
+                let global = function
+                    .global()
+                    .ok_or_else(|| ErrorKind::Unsupported(format!("writing function {}", function.name())))?;
+                self.save_global(&global)?;
+            }
+            Value::PersistentLoad(pid) => {
+                tasks.extend([Task::Save(pid), Task::Op(OpCode::Binpersid)]);
+            }
+            Value::Reduce(func, args) => {
+                tasks.extend([Task::Save(func), Task::Save(args), Task::Op(OpCode::Reduce)]);
+            }
+            Value::SetState(inst, state) => {
+                tasks.extend([Task::Save(inst), Task::Save(state), Task::Op(OpCode::Build)]);
+            }
+            Value::Storage(storage) => self.save_storage(storage)?,
+            Value::Tensor(tensor) => self.save_tensor(tensor)?,
+        }
+
+        Ok(())
+    }
+
+    fn save_int(&mut self, value: i64) {
+        if let Ok(value) = u8::try_from(value) {
+            self.write(&[OpCode::Binint1.into(), value]);
+        }
+        else if let Ok(value) = u16::try_from(value) {
+            self.op(OpCode::Binint2);
+            self.write(&value.to_le_bytes());
+        }
+        else if let Ok(value) = i32::try_from(value) {
+            self.op(OpCode::Binint);
+            self.write(&value.to_le_bytes());
+        }
+        else {
+            let data = BigInt::from(value).to_signed_bytes_le();
+            self.write(&[OpCode::Long1.into(), data.len() as u8]);
+            self.write(&data);
+        }
+    }
+
+    fn save_long(&mut self, value: &BigInt) -> Result<()> {
+        let data = value.to_signed_bytes_le();
+        if let Ok(len) = u8::try_from(data.len()) {
+            self.write(&[OpCode::Long1.into(), len]);
+        }
+        else {
+            let len = i32::try_from(data.len()).map_err(|_| too_long("int"))?;
+            self.op(OpCode::Long4);
+            self.write(&len.to_le_bytes());
+        }
+        self.write(&data);
+
+        Ok(())
+    }
+
+    fn save_str(&mut self, s: &str) -> Result<()> {
+        let key = MemoKey::String(s.to_string());
+        if self.get(&key) {
+            return Ok(());
+        }
+
+        let data = s.as_bytes();
+        let mut header = Vec::with_capacity(9);
+        if self.protocol >= 4 && data.len() < 256 {
+            header.extend_from_slice(&[OpCode::ShortBinunicode.into(), data.len() as u8]);
+        }
+        else if let Ok(len) = u32::try_from(data.len()) {
+            header.push(OpCode::Binunicode.into());
+            header.write_u32::<LittleEndian>(len)?;
+        }
+        else if self.protocol >= 4 {
+            header.push(OpCode::Binunicode8.into());
+            header.write_u64::<LittleEndian>(data.len() as u64)?;
+        }
+        else {
+            return Err(too_long("str"));
+        }
+        self.write_payload(&header, data)?;
+
+        self.memoize(key);
+        Ok(())
+    }
+
+    fn save_bytes(&mut self, data: &[u8]) -> Result<()> {
+        let key = MemoKey::Bytes(data.to_vec());
+        if self.get(&key) {
+            return Ok(());
+        }
+
+        if self.protocol < 3 {
+            // Protocol 2 has no bytes type, so python rebuilds them from a
+            // latin-1 string.
+            self.save_global(&Global::new("_codecs", "encode"))?;
+            self.save_str(&latin1(data))?;
+            self.save_str("latin1")?;
+            self.op(OpCode::Tuple2);
+            self.op(OpCode::Reduce);
+        }
+        else {
+            let mut header = Vec::with_capacity(9);
+            if data.len() < 256 {
+                header.extend_from_slice(&[OpCode::ShortBinbytes.into(), data.len() as u8]);
+            }
+            else if let Ok(len) = u32::try_from(data.len()) {
+                header.push(OpCode::Binbytes.into());
+                header.write_u32::<LittleEndian>(len)?;
+            }
+            else if self.protocol >= 4 {
+                header.push(OpCode::Binbytes8.into());
+                header.write_u64::<LittleEndian>(data.len() as u64)?;
+            }
+            else {
+                return Err(too_long("bytes"));
+            }
+            self.write_payload(&header, data)?;
+        }
+
+        self.memoize(key);
+        Ok(())
+    }
+
+    fn save_bytearray(&mut self, data: &[u8]) -> Result<()> {
+        if self.protocol >= 5 {
+            let mut header = vec![OpCode::Bytearray8.into()];
+            header.write_u64::<LittleEndian>(data.len() as u64)?;
+            return self.write_payload(&header, data);
+        }
+
+        self.save_global(&Global::new("builtins", "bytearray"))?;
+        if self.protocol < 3 {
+            self.save_str(&latin1(data))?;
+            self.save_str("latin-1")?;
+            self.op(OpCode::Tuple2);
+        }
+        else {
+            self.save_bytes(data)?;
+            self.op(OpCode::Tuple1);
+        }
+        self.op(OpCode::Reduce);
+
+        Ok(())
+    }
+
+    fn save_tuple<'a>(&mut self, items: &'a [Value], tasks: &mut Vec<Task<'a>>) {
+        let opcode = match items.len() {
+            0 => {
+                self.op(OpCode::EmptyTuple);
+                return;
+            }
+            1 => OpCode::Tuple1,
+            2 => OpCode::Tuple2,
+            3 => OpCode::Tuple3,
+            _ => {
+                self.op(OpCode::Mark);
+                OpCode::Tuple
+            }
+        };
+
+        tasks.extend(items.iter().map(Task::Save));
+        tasks.push(Task::Op(opcode));
+    }
+
+    fn save_set<'a>(&mut self, set: &'a Set, frozen: bool, tasks: &mut Vec<Task<'a>>) -> Result<()> {
+        if self.protocol < 4 {
+            // Sets have their own ops from protocol 4, before that they are
+            // built from a list.
+            let name = if frozen { "frozenset" } else { "set" };
+            self.save_global(&Global::new("builtins", name))?;
+            self.op(OpCode::EmptyList);
+            batched(OpCode::Append, OpCode::Appends, set.iter().map(|item| [Task::Save(item)]), tasks);
+            tasks.extend([Task::Op(OpCode::Tuple1), Task::Op(OpCode::Reduce)]);
+        }
+        else if frozen {
+            self.op(OpCode::Mark);
+            tasks.extend(set.iter().map(Task::Save));
+            tasks.push(Task::Op(OpCode::Frozenset));
+        }
+        else {
+            self.op(OpCode::EmptySet);
+            for batch in &set.iter().chunks(BATCH_SIZE) {
+                tasks.push(Task::Op(OpCode::Mark));
+                tasks.extend(batch.map(Task::Save));
+                tasks.push(Task::Op(OpCode::Additems));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save_global(&mut self, global: &Global) -> Result<()> {
+        let key = MemoKey::Global(global.clone());
+        if self.get(&key) {
+            return Ok(());
+        }
+
+        let module = PY2_MODULES
+            .iter()
+            .find_map(|&(py3, py2)| {
+                let (from, to) = if self.protocol < 3 { (py3, py2) } else { (py2, py3) };
+                (global.module() == from).then_some(to)
+            })
+            .unwrap_or(global.module());
+
+        if self.protocol >= 4 {
+            self.save_str(module)?;
+            self.save_str(global.name())?;
+            self.op(OpCode::StackGlobal);
+        }
+        else {
+            if module.contains('\n') || global.name().contains('\n') {
+                return Err(ErrorKind::InvalidArgument(format!("newline in global {}", global)).into());
+            }
+            self.op(OpCode::Global);
+            self.write(format!("{}\n{}\n", module, global.name()).as_bytes());
+        }
+
+        self.memoize(key);
+        Ok(())
+    }
+
+    /// `('storage', torch.<Type>Storage, key, location, numel)`, as a
+    /// persistent id, with a trailing `None` view for legacy checkpoints.
+    fn save_storage(&mut self, storage: &Storage) -> Result<()> {
+        self.op(OpCode::Mark);
+        self.save_str("storage")?;
+        self.save_global(&Global::new("torch", storage.dtype.storage_name()))?;
+        self.save_str(&storage.key)?;
+        self.save_str(&storage.location)?;
+        self.save(&Value::from(BigInt::from(storage.numel)))?;
+        if storage.has_view_metadata {
+            self.op(OpCode::None);
+        }
+        self.op(OpCode::Tuple);
+        self.op(OpCode::Binpersid);
+
+        Ok(())
+    }
+
+    /// `_rebuild_tensor_v2(storage, storage_offset, size, stride,
+    /// requires_grad, OrderedDict())`, wrapped in `_rebuild_parameter(data,
+    /// requires_grad, OrderedDict())` for a parameter.
+    fn save_tensor(&mut self, tensor: &Tensor) -> Result<()> {
+        let dims = |dims: &[u64]| Value::Tuple(dims.iter().map(|&dim| Value::from(BigInt::from(dim))).collect());
+
+        // Like torch, a parameter wraps its data, which never requires grad
+        // itself.
+        if tensor.parameter {
+            self.save_global(&Global::new("torch._utils", "_rebuild_parameter"))?;
+            self.op(OpCode::Mark);
+        }
+
+        self.save_global(&Global::new("torch._utils", "_rebuild_tensor_v2"))?;
+        self.op(OpCode::Mark);
+        self.save_storage(&tensor.storage)?;
+        self.save(&Value::from(BigInt::from(tensor.storage_offset)))?;
+        self.save(&dims(&tensor.shape))?;
+        self.save(&dims(&tensor.strides))?;
+        self.save(&Value::Bool(tensor.requires_grad && !tensor.parameter))?;
+        self.save(&Value::OrderedDict(Default::default()))?;
+        self.op(OpCode::Tuple);
+        self.op(OpCode::Reduce);
+
+        if tensor.parameter {
+            self.save(&Value::Bool(tensor.requires_grad))?;
+            self.save(&Value::OrderedDict(Default::default()))?;
+            self.op(OpCode::Tuple);
+            self.op(OpCode::Reduce);
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds `items` to the container on top of the stack, a single one with
+/// `one` and the rest in batches with `many`.
+fn batched<'a, T: IntoIterator<Item = Task<'a>>>(
+    one: OpCode,
+    many: OpCode,
+    items: impl ExactSizeIterator<Item = T>,
+    tasks: &mut Vec<Task<'a>>,
+) {
+    if items.len() == 1 {
+        tasks.extend(items.flatten());
+        tasks.push(Task::Op(one));
+        return;
+    }
+
+    let mut items = items.peekable();
+    while items.peek().is_some() {
+        tasks.push(Task::Op(OpCode::Mark));
+        tasks.extend(items.by_ref().take(BATCH_SIZE).flatten());
+        tasks.push(Task::Op(many));
+    }
+}
+
+fn item_tasks<'a>((key, value): (&'a Value, &'a Value)) -> [Task<'a>; 2] {
+    [Task::Save(key), Task::Save(value)]
+}
+
+fn latin1(data: &[u8]) -> String {
+    data.iter().map(|&byte| byte as char).collect()
+}
+
+fn too_long(what: &str) -> Error {
+    ErrorKind::Unsupported(format!("{} too long for the pickle protocol", what)).into()
+}
+
@@ -0,0 +1,184 @@
+use std::{fmt, io};
+
+use crate::interpreter::Global;
+use crate::opcodes::OpCode;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What went wrong while decoding or interpreting a pickle.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    #[error("unexpected end of input")]
+    Truncated,
+    #[error("unknown opcode {0:#04x}")]
+    UnknownOpcode(u8),
+    #[error("stack underflow")]
+    StackUnderflow,
+    #[error("could not find MARK")]
+    MissingMark,
+    #[error("memo value not found at index {0}")]
+    MemoMiss(u32),
+    #[error("invalid UTF-8 in string")]
+    InvalidUtf8,
+    #[error("type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("global {0} is not allowed")]
+    PolicyViolation(Global),
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("invalid frame: {0}")]
+    InvalidFrame(String),
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    #[error("trailing data after the last pickle: {0}")]
+    TrailingData(String),
+    #[error("could not deserialize: {0}")]
+    Deserialize(String),
+    #[error("could not serialize: {0}")]
+    Serialize(String),
+    #[error("{limit} limit of {max} exceeded")]
+    LimitExceeded { limit: Limit, max: u64 },
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+/// The resource limit behind an [`ErrorKind::LimitExceeded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum Limit {
+    #[error("payload length")]
+    PayloadLen,
+    #[error("frame length")]
+    FrameLen,
+    #[error("allocation")]
+    AllocBytes,
+    #[error("op count")]
+    Ops,
+    #[error("stack depth")]
+    StackDepth,
+    #[error("metastack depth")]
+    MetastackDepth,
+    #[error("memo size")]
+    MemoEntries,
+    #[error("container size")]
+    ContainerLen,
+    #[error("nesting depth")]
+    NestingDepth,
+    #[error("tensor size")]
+    TensorBytes,
+}
+
+impl Limit {
+    pub(crate) fn exceeded(self, max: impl TryInto<u64>) -> Error {
+        let max = max.try_into().unwrap_or(u64::MAX);
+        ErrorKind::LimitExceeded { limit: self, max }.into()
+    }
+}
+
+/// An [`ErrorKind`] together with where in the pickle it happened.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    offset: Option<u64>,
+    opcode: Option<OpCode>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Error {
+            kind,
+            offset: None,
+            opcode: None,
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Byte offset of the start of the op that failed, `None` for errors
+    /// from [`Interpreter::exec_op`](crate::Interpreter::exec_op) called
+    /// directly.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    pub fn opcode(&self) -> Option<OpCode> {
+        self.opcode
+    }
+
+    /// Records the position of the failing op, unless one is already known.
+    pub fn at(mut self, offset: u64, opcode: Option<OpCode>) -> Self {
+        if self.offset.is_none() {
+            self.offset = Some(offset);
+            self.opcode = opcode;
+        }
+        self
+    }
+
+    /// Rewords an error decoding input found after a complete pickle as
+    /// trailing data starting at `start`. I/O errors and exceeded limits are
+    /// kept as they are.
+    pub(crate) fn into_trailing_data(self, start: u64) -> Self {
+        match self.kind {
+            ErrorKind::Io(_) | ErrorKind::LimitExceeded { .. } => self,
+            kind => Error::from(ErrorKind::TrailingData(kind.to_string())).at(start, None),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {offset}")?;
+        }
+        if let Some(opcode) = self.opcode {
+            write!(f, " ({opcode:?})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ErrorKind::Truncated.into(),
+            _ => ErrorKind::Io(err).into(),
+        }
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ErrorKind::Deserialize(msg.to_string()).into()
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ErrorKind::Serialize(msg.to_string()).into()
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(_: std::string::FromUtf8Error) -> Self {
+        ErrorKind::InvalidUtf8.into()
+    }
+}
@@ -1,20 +1,225 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::hash::Hash;
 use std::sync::Arc;
 use std::{fmt, mem};
 
 use crate::ast::Op;
-use eyre::{eyre, Result};
+use crate::error::{ErrorKind, Limit, Result};
+use crate::policy::{GlobalPolicy, PolicyMode};
+use crate::tensor::{self, Storage, Tensor};
 use itertools::Itertools;
+use num_bigint::BigInt;
 
+/// A python `dict`, kept as its key/value pairs in insertion order.
 #[derive(Clone, Default)]
-struct Dict(Vec<(Value, Value)>);
+pub struct Dict(Items);
+
+impl Dict {
+    pub fn len(&self) -> usize {
+        self.0.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.entries.is_empty()
+    }
+
+    /// Iterates over the key/value pairs in insertion order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&Value, &Value)> {
+        self.0.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = (&mut Value, &mut Value)> {
+        self.0.index = KeyIndex::default();
+        self.0.entries.iter_mut().map(|(k, v)| (k, v))
+    }
+
+    /// Looks up the value stored under a string key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        find_str_key(&self.0.entries, key)
+    }
+
+    pub fn into_inner(self) -> Vec<(Value, Value)> {
+        self.0.entries
+    }
+}
+
+impl From<Vec<(Value, Value)>> for Dict {
+    fn from(entries: Vec<(Value, Value)>) -> Self {
+        Dict(Items {
+            entries,
+            index: KeyIndex::default(),
+        })
+    }
+}
+
+/// The key/value pairs of a dict, indexed so that setting an existing key
+/// replaces its value the way python does.
+#[derive(Clone, Default)]
+struct Items {
+    entries: Vec<(Value, Value)>,
+    index: KeyIndex,
+}
+
+impl Items {
+    fn insert(&mut self, key: Value, value: Value) {
+        match self.index.find_or_add(self.entries.iter().map(|(k, _)| k), &key) {
+            // Python keeps the key it already has and only replaces the value.
+            Some(i) => self.entries[i].1 = value,
+            None => self.entries.push((key, value)),
+        }
+    }
+}
+
+/// A python `set` or `frozenset`, kept as its members in insertion order.
+#[derive(Clone, Default)]
+pub struct Set {
+    items: Vec<Value>,
+    index: KeyIndex,
+}
+
+impl Set {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &Value> {
+        self.items.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = &mut Value> {
+        self.index = KeyIndex::default();
+        self.items.iter_mut()
+    }
+
+    pub fn into_inner(self) -> Vec<Value> {
+        self.items
+    }
+
+    fn insert(&mut self, item: Value) {
+        if self.index.find_or_add(self.items.iter(), &item).is_none() {
+            self.items.push(item);
+        }
+    }
+}
+
+impl From<Vec<Value>> for Set {
+    fn from(items: Vec<Value>) -> Self {
+        let mut set = Set::default();
+        for item in items {
+            set.insert(item);
+        }
+        set
+    }
+}
+
+impl fmt::Debug for Set {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.items.iter()).finish()
+    }
+}
+
+/// Position of each hashable dict key or set member. Built on the first
+/// insert, and dropped when the container is copied or its keys may have
+/// changed.
+#[derive(Default)]
+struct KeyIndex(Option<HashMap<ItemKey, usize>>);
+
+impl Clone for KeyIndex {
+    fn clone(&self) -> Self {
+        KeyIndex(None)
+    }
+}
+
+impl KeyIndex {
+    /// Where a key equal to `key` is among `keys`. If there is none, `key` is
+    /// recorded as the one about to be appended after them.
+    fn find_or_add<'a>(&mut self, mut keys: impl ExactSizeIterator<Item = &'a Value>, key: &Value) -> Option<usize> {
+        let next = keys.len();
+        let index = self
+            .0
+            .get_or_insert_with(|| keys.by_ref().enumerate().filter_map(|(i, key)| Some((ItemKey::of(key)?, i))).collect());
+
+        // Keys that are not plain values never compare equal to another key.
+        let key = ItemKey::of(key)?;
+        match index.get(&key) {
+            Some(&i) => Some(i),
+            None => {
+                index.insert(key, next);
+                None
+            }
+        }
+    }
+}
+
+/// A dict key as python hashes and compares it, so `1`, `1.0` and `True` are
+/// the same key. Only built for values python could hash by value.
+#[derive(PartialEq, Eq, Hash)]
+enum ItemKey {
+    None,
+    Int(BigInt),
+    Float(u64),
+    String(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<ItemKey>),
+    Global(Global),
+}
+
+impl ItemKey {
+    fn of(value: &Value) -> Option<ItemKey> {
+        let key = match value {
+            Value::None => ItemKey::None,
+            Value::Bool(value) => ItemKey::Int(BigInt::from(*value as u8)),
+            Value::Int(value) => ItemKey::Int(BigInt::from(*value)),
+            Value::BigInt(value) => ItemKey::Int(value.clone()),
+            Value::Float(value) if value.is_nan() => return None,
+            Value::Float(value) => match integral_float(*value) {
+                Some(value) => ItemKey::Int(value),
+                None => ItemKey::Float(value.to_bits()),
+            },
+            Value::String(value) => ItemKey::String(value.clone()),
+            Value::Bytes(value) => ItemKey::Bytes(value.clone()),
+            Value::Tuple(items) => ItemKey::Tuple(items.iter().map(ItemKey::of).collect::<Option<_>>()?),
+            Value::Global(global) => ItemKey::Global(global.clone()),
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+/// The integer a finite float with no fractional part is equal to.
+fn integral_float(value: f64) -> Option<BigInt> {
+    if !value.is_finite() || value.fract() != 0.0 {
+        return None;
+    }
+    // Below 2^52 every float converts exactly; above it every float is an
+    // integer, its mantissa shifted left by a non-negative exponent.
+    if value.abs() < (1u64 << 52) as f64 {
+        return Some(BigInt::from(value as i64));
+    }
+    let bits = value.to_bits();
+    let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+    let exponent = ((bits >> 52) & 0x7ff) - 1075;
+    let magnitude = BigInt::from(mantissa) << exponent;
+    Some(if value < 0.0 { -magnitude } else { magnitude })
+}
+
+fn find_str_key<'a>(items: &'a [(Value, Value)], key: &str) -> Option<&'a Value> {
+    items
+        .iter()
+        .rev()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v)
+}
 
 impl fmt::Debug for Dict {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_map()
-            .entries(self.0.iter().map(|&(ref k, ref v)| (k, v)))
+            .entries(self.0.entries.iter().map(|(k, v)| (k, v)))
             .finish()
     }
 }
@@ -24,25 +229,142 @@ const ORDERED_DICT_NAME: Global = Global {
     name: Cow::Borrowed("OrderedDict"),
 };
 
-fn ordered_dict_constructor(interp: &mut Interpreter, args: Value) -> Result<Value> {
+/// What NEWOBJ_EX reduces to when it has keyword arguments, as in python's
+/// own `__reduce_ex__`.
+const NEWOBJ_EX_NAME: Global = Global {
+    module: Cow::Borrowed("copyreg"),
+    name: Cow::Borrowed("__newobj_ex__"),
+};
+
+fn ordered_dict_constructor(_interp: &mut Interpreter, args: Value) -> Result<Value> {
     match args {
-        Value::Tuple(args) if args.is_empty() => {
-            return Ok(Value::OrderedDict(OrderedDict::default()))
-        }
-        other => {
-            return Err(eyre!("Unexpected arguments for OrderedDict"));
-        }
+        Value::Tuple(args) if args.is_empty() => Ok(Value::OrderedDict(OrderedDict::default())),
+        _ => Err(ErrorKind::TypeMismatch("unexpected arguments for OrderedDict".to_string()).into()),
+    }
+}
+
+/// `set`, `frozenset` and `bytearray` as written below protocol 4 and 5, and
+/// `_codecs.encode` and `bytes` which protocol 2 writes bytes with, the
+/// latter only empty ones. Each is bound under its python 2 module name too,
+/// which protocol 2 pickles use.
+const BUILTIN_FUNCTIONS: &[(&str, &str, Builtin)] = &[
+    ("builtins", "set", set_constructor),
+    ("builtins", "frozenset", frozenset_constructor),
+    ("builtins", "bytes", bytes_constructor),
+    ("builtins", "bytearray", bytearray_constructor),
+    ("__builtin__", "set", set_constructor),
+    ("__builtin__", "frozenset", frozenset_constructor),
+    ("__builtin__", "bytes", bytes_constructor),
+    ("__builtin__", "bytearray", bytearray_constructor),
+    ("_codecs", "encode", codecs_encode),
+];
+
+type Builtin = fn(&mut Interpreter, Value) -> Result<Value>;
+
+/// The items of the one iterable a set is built from, if any.
+fn set_items(what: &str, args: Value) -> Result<Vec<Value>> {
+    match args {
+        Value::Tuple(mut args) if args.len() <= 1 => match args.pop() {
+            None => Ok(Vec::new()),
+            Some(Value::List(items) | Value::Tuple(items)) => Ok(items),
+            Some(Value::Set(set) | Value::FrozenSet(set)) => Ok(set.into_inner()),
+            Some(_) => Err(ErrorKind::TypeMismatch(format!("unexpected arguments for {}", what)).into()),
+        },
+        _ => Err(ErrorKind::TypeMismatch(format!("unexpected arguments for {}", what)).into()),
     }
 }
 
+fn set_constructor(_interp: &mut Interpreter, args: Value) -> Result<Value> {
+    Ok(Value::Set(set_items("set", args)?.into()))
+}
+
+fn frozenset_constructor(_interp: &mut Interpreter, args: Value) -> Result<Value> {
+    Ok(Value::FrozenSet(set_items("frozenset", args)?.into()))
+}
+
+/// The data of a `bytes` or `bytearray` built from its arguments.
+fn byte_string(what: &str, args: Value) -> Result<Vec<u8>> {
+    match args {
+        Value::Tuple(args) => match args.as_slice() {
+            [] => Ok(Vec::new()),
+            [Value::Bytes(data) | Value::ByteArray(data)] => Ok(data.clone()),
+            // Python 2 writes `bytearray(text, encoding)`.
+            [Value::String(text), Value::String(encoding)] => encode(text, encoding),
+            _ => Err(ErrorKind::TypeMismatch(format!("unexpected arguments for {}", what)).into()),
+        },
+        _ => Err(ErrorKind::TypeMismatch(format!("unexpected arguments for {}", what)).into()),
+    }
+}
+
+fn bytes_constructor(_interp: &mut Interpreter, args: Value) -> Result<Value> {
+    Ok(Value::Bytes(byte_string("bytes", args)?))
+}
+
+fn bytearray_constructor(_interp: &mut Interpreter, args: Value) -> Result<Value> {
+    Ok(Value::ByteArray(byte_string("bytearray", args)?))
+}
+
+fn codecs_encode(_interp: &mut Interpreter, args: Value) -> Result<Value> {
+    match args {
+        Value::Tuple(args) => match args.as_slice() {
+            [Value::String(text)] => Ok(Value::Bytes(encode(text, "utf-8")?)),
+            [Value::String(text), Value::String(encoding)] => Ok(Value::Bytes(encode(text, encoding)?)),
+            _ => Err(ErrorKind::TypeMismatch("unexpected arguments for _codecs.encode".to_string()).into()),
+        },
+        _ => Err(ErrorKind::TypeMismatch("unexpected arguments for _codecs.encode".to_string()).into()),
+    }
+}
+
+/// Encodes `text` with the encodings pickles use for bytes.
+fn encode(text: &str, encoding: &str) -> Result<Vec<u8>> {
+    match encoding.to_ascii_lowercase().replace('_', "-").as_str() {
+        "latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" | "l1" => text
+            .chars()
+            .map(|c| u8::try_from(c).map_err(|_| ErrorKind::TypeMismatch(format!("{:?} is not latin-1", c)).into()))
+            .collect(),
+        "utf-8" | "utf8" => Ok(text.as_bytes().to_vec()),
+        _ => Err(ErrorKind::Unsupported(format!("encoding {}", encoding)).into()),
+    }
+}
+
+/// A `collections.OrderedDict`, kept as its key/value pairs in insertion order.
 #[derive(Clone, Default)]
-struct OrderedDict(Vec<(Value, Value)>);
+pub struct OrderedDict(Items);
+
+impl OrderedDict {
+    pub fn len(&self) -> usize {
+        self.0.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.entries.is_empty()
+    }
+
+    /// Iterates over the key/value pairs in insertion order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&Value, &Value)> {
+        self.0.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = (&mut Value, &mut Value)> {
+        self.0.index = KeyIndex::default();
+        self.0.entries.iter_mut().map(|(k, v)| (k, v))
+    }
+
+    /// Looks up the value stored under a string key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        find_str_key(&self.0.entries, key)
+    }
+
+    pub fn into_inner(self) -> Vec<(Value, Value)> {
+        self.0.entries
+    }
+}
 
 impl fmt::Debug for OrderedDict {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "OrderedDict ")?;
         f.debug_map()
-            .entries(self.0.iter().map(|&(ref k, ref v)| (k, v)))
+            .entries(self.0.entries.iter().map(|(k, v)| (k, v)))
             .finish()
     }
 }
@@ -52,11 +374,24 @@ trait FunctionDef {
     fn call(&self, interpreter: &mut Interpreter, value: Value) -> Result<Value>;
 }
 
+/// A native rust implementation of a python callable, bound to a global with
+/// [`Interpreter::set_global`]. Built-in functions are named after the dotted
+/// path of the global they are bound to.
 #[derive(Clone)]
-struct Function(Arc<dyn FunctionDef>);
+pub struct Function(Arc<dyn FunctionDef>);
 
 impl Function {
-    fn call(&self, interpreter: &mut Interpreter, value: Value) -> Result<Value> {
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    /// The global a built-in function is bound to, read back from its name.
+    pub(crate) fn global(&self) -> Option<Global> {
+        let (module, name) = self.name().rsplit_once('.')?;
+        Some(Global::new(module.to_string(), name.to_string()))
+    }
+
+    pub fn call(&self, interpreter: &mut Interpreter, value: Value) -> Result<Value> {
         self.0.call(interpreter, value)
     }
 }
@@ -91,40 +426,197 @@ impl Function {
     }
 }
 
+/// A reference to a python object by module and name, as produced by the
+/// GLOBAL and STACK_GLOBAL opcodes.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Global {
     module: Cow<'static, str>,
     name: Cow<'static, str>,
 }
 
+impl Global {
+    pub fn new(module: impl Into<Cow<'static, str>>, name: impl Into<Cow<'static, str>>) -> Self {
+        Global {
+            module: module.into(),
+            name: name.into(),
+        }
+    }
+
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.module, self.name)
+    }
+}
+
+/// A depickled python object.
+///
+/// Only plain data is ever materialised. Calls to unknown callables are kept
+/// as inert [`Value::Reduce`] and [`Value::SetState`] nodes rather than run.
 #[derive(Clone)]
 pub enum Value {
-    U32(u32),
-    I32(i32),
+    None,
+    Int(i64),
+    BigInt(BigInt),
+    Float(f64),
     String(String),
+    Bytes(Vec<u8>),
+    ByteArray(Vec<u8>),
     Bool(bool),
     Dict(Dict),
     OrderedDict(OrderedDict),
     Tuple(Vec<Value>),
     List(Vec<Value>),
+    Set(Set),
+    FrozenSet(Set),
     Global(Global),
     PersistentLoad(Box<Value>),
     Reduce(Box<Value>, Box<Value>),
     Function(Function),
     SetState(Box<Value>, Box<Value>),
+    /// A torch storage resolved from its persistent id.
+    Storage(Storage),
+    /// A torch tensor rebuilt over a [`Value::Storage`].
+    Tensor(Tensor),
+}
+
+impl Value {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Value::None)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer as an `i64` if it is one and fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(value) => Some(*value),
+            Value::BigInt(value) => i64::try_from(value).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer as a `u64` if it is one and fits.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Int(value) => u64::try_from(*value).ok(),
+            Value::BigInt(value) => u64::try_from(value).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer at full precision.
+    pub fn as_bigint(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(value) => Some(BigInt::from(*value)),
+            Value::BigInt(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a `bytes` or `bytearray`.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(value) | Value::ByteArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_tuple(&self) -> Option<&[Value]> {
+        match self {
+            Value::Tuple(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_set(&self) -> Option<&Set> {
+        match self {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    pub fn as_frozenset(&self) -> Option<&Set> {
+        match self {
+            Value::FrozenSet(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Value::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_ordered_dict(&self) -> Option<&OrderedDict> {
+        match self {
+            Value::OrderedDict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_global(&self) -> Option<&Global> {
+        match self {
+            Value::Global(global) => Some(global),
+            _ => None,
+        }
+    }
+
+    pub fn as_tensor(&self) -> Option<&Tensor> {
+        match self {
+            Value::Tensor(tensor) => Some(tensor),
+            _ => None,
+        }
+    }
 }
 
 impl Value {
     fn set_item(&mut self, key: Value, value: Value) -> Result<()> {
         match self {
             Value::Dict(d) => {
-                d.0.push((key, value));
+                d.0.insert(key, value);
             }
             Value::OrderedDict(d) => {
-                d.0.push((key, value));
+                d.0.insert(key, value);
             }
-            other => {
-                return Err(eyre!("Type can not be have items set"));
+            _ => {
+                return Err(ErrorKind::TypeMismatch("type can not have items set".to_string()).into());
             }
         }
 
@@ -136,13 +628,34 @@ impl Value {
     {
         match self {
             Value::Dict(d) => {
-                d.0.extend(items)
+                for (key, value) in items {
+                    d.0.insert(key, value);
+                }
             }
             Value::OrderedDict(d) => {
-                d.0.extend(items)
+                for (key, value) in items {
+                    d.0.insert(key, value);
+                }
+            }
+            _ => {
+                return Err(ErrorKind::TypeMismatch("type can not have items set".to_string()).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_items<I>(&mut self, items: I) -> Result<()>
+        where I: Iterator<Item=Value>
+    {
+        match self {
+            Value::Set(s) => {
+                for item in items {
+                    s.insert(item);
+                }
             }
-            other => {
-                return Err(eyre!("Type can not be have items set"));
+            _ => {
+                return Err(ErrorKind::TypeMismatch("type can not have items added".to_string()).into());
             }
         }
 
@@ -152,10 +665,10 @@ impl Value {
     fn extend(&mut self, items: Vec<Value>) -> Result<()> {
         match self {
             Value::List(existing_items) => {
-                existing_items.extend(items.into_iter());
+                existing_items.extend(items);
             }
-            other => {
-                return Err(eyre!("Type can not be appended/extended"));
+            _ => {
+                return Err(ErrorKind::TypeMismatch("type can not be appended/extended".to_string()).into());
             }
         }
 
@@ -166,19 +679,32 @@ impl Value {
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::U32(arg0) => write!(f, "{:?}", arg0),
-            Self::I32(arg0) => write!(f, "{:?}", arg0),
+            Self::None => write!(f, "None"),
+            Self::Int(arg0) => write!(f, "{:?}", arg0),
+            Self::BigInt(arg0) => write!(f, "{}", arg0),
+            Self::Float(arg0) => write!(f, "{:?}", arg0),
             Self::String(arg0) => write!(f, "{:?}", arg0),
+            Self::Bytes(arg0) => write!(f, "b\"{}\"", arg0.escape_ascii()),
+            Self::ByteArray(arg0) => write!(f, "bytearray(b\"{}\")", arg0.escape_ascii()),
             Self::Bool(b) => write!(f, "{:?}", b),
             Self::Dict(arg0) => if f.alternate() { write!(f, "{:#?}", arg0) } else { write!(f, "{:?}", arg0) },
             Self::OrderedDict(arg0) => if f.alternate() { write!(f, "{:#?}", arg0) } else { write!(f, "{:?}", arg0) },
             Self::Function(arg0) => write!(f, "{:?}", arg0),
             Self::Tuple(arg0) => f.debug_list().entries(arg0.iter()).finish(), //f.debug_tuple("Tuple").field(arg0).finish(),
             Self::List(arg0) => f.debug_list().entries(arg0.iter()).finish(), //f.debug_tuple("Tuple").field(arg0).finish(),
+            Self::Set(arg0) if arg0.is_empty() => write!(f, "set()"),
+            Self::Set(arg0) => f.debug_set().entries(arg0.iter()).finish(),
+            Self::FrozenSet(arg0) => {
+                write!(f, "frozenset(")?;
+                f.debug_set().entries(arg0.iter()).finish()?;
+                write!(f, ")")
+            }
             Self::Global(arg0) => write!(f, "{:?}", arg0),
             Self::PersistentLoad(arg0) => f.debug_tuple("PersistentLoad").field(arg0).finish(),
             Self::Reduce(func, args) => f.debug_tuple("Reduce").field(func).field(args).finish(),
             Self::SetState(inst, state) => f.debug_tuple("SetState").field(inst).field(state).finish(),
+            Self::Storage(arg0) => write!(f, "{:?}", arg0),
+            Self::Tensor(arg0) => if f.alternate() { write!(f, "{:#?}", arg0) } else { write!(f, "{:?}", arg0) },
         }
     }
 }
@@ -195,21 +721,44 @@ impl From<String> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
 impl From<i32> for Value {
     fn from(value: i32) -> Self {
-        Value::I32(value)
+        Value::Int(value.into())
+    }
+}
+
+impl From<u8> for Value {
+    fn from(value: u8) -> Self {
+        Value::Int(value.into())
     }
 }
 
-impl From<i8> for Value {
-    fn from(value: i8) -> Self {
-        Value::I32(value as i32)
+impl From<u16> for Value {
+    fn from(value: u16) -> Self {
+        Value::Int(value.into())
     }
 }
 
-impl From<i16> for Value {
-    fn from(value: i16) -> Self {
-        Value::I32(value as i32)
+impl From<BigInt> for Value {
+    /// Integers that fit in an `i64` are stored as [`Value::Int`] so there is
+    /// only one representation of each number.
+    fn from(value: BigInt) -> Self {
+        match i64::try_from(&value) {
+            Ok(small) => Value::Int(small),
+            Err(_) => Value::BigInt(value),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
     }
 }
 
@@ -219,165 +768,605 @@ impl From<bool> for Value {
     }
 }
 
+/// Cached size and nesting depth of a value on the stack or in the memo, kept
+/// up to date as containers grow so that checking limits never walks a value.
+#[derive(Debug, Clone, Copy, Default)]
+struct Footprint {
+    /// Rough number of bytes a deep copy of the value occupies. Items replaced
+    /// in a dict are still counted, so this is an upper bound.
+    size: u64,
+    /// Levels of containers below the value, 0 for scalars and empty ones.
+    depth: usize,
+}
+
+impl Footprint {
+    /// Measures `value` with an explicit stack, so it can not overflow the
+    /// call stack however deeply the value is nested.
+    fn of(value: &Value) -> Footprint {
+        let mut footprint = Footprint::default();
+        let mut pending = vec![(value, 0)];
+
+        while let Some((value, level)) = pending.pop() {
+            footprint.size = footprint.size.saturating_add(Footprint::shallow_size(value));
+            footprint.depth = footprint.depth.max(level);
+
+            match value {
+                Value::Dict(d) => pending.extend(d.0.entries.iter().flat_map(|(k, v)| [(k, level + 1), (v, level + 1)])),
+                Value::OrderedDict(d) => pending.extend(d.0.entries.iter().flat_map(|(k, v)| [(k, level + 1), (v, level + 1)])),
+                Value::Tuple(items) | Value::List(items) => pending.extend(items.iter().map(|item| (item, level + 1))),
+                Value::Set(set) | Value::FrozenSet(set) => pending.extend(set.iter().map(|item| (item, level + 1))),
+                Value::PersistentLoad(value) => pending.push((value, level + 1)),
+                Value::Reduce(a, b) | Value::SetState(a, b) => pending.extend([(&**a, level + 1), (&**b, level + 1)]),
+                _ => {}
+            }
+        }
+
+        footprint
+    }
+
+    /// A container holding values with the given footprints.
+    fn container(value: &Value, items: impl IntoIterator<Item = Footprint>) -> Footprint {
+        let mut footprint = Footprint {
+            size: Footprint::shallow_size(value),
+            depth: 0,
+        };
+        footprint.add(items);
+        footprint
+    }
+
+    /// Accounts for `items` having been put into the container.
+    fn add(&mut self, items: impl IntoIterator<Item = Footprint>) {
+        for item in items {
+            self.size = self.size.saturating_add(item.size);
+            self.depth = self.depth.max(item.depth + 1);
+        }
+    }
+
+    /// Bytes the value occupies itself, not counting the values it holds.
+    fn shallow_size(value: &Value) -> u64 {
+        let own = mem::size_of::<Value>() as u64;
+
+        own + match value {
+            Value::BigInt(value) => value.bits() / 8,
+            Value::String(s) => s.len() as u64,
+            Value::Bytes(b) | Value::ByteArray(b) => b.len() as u64,
+            Value::Storage(storage) => (storage.key.len() + storage.location.len()) as u64,
+            Value::Tensor(tensor) => {
+                ((tensor.shape.len() + tensor.strides.len()) * 8 + tensor.storage.key.len() + tensor.storage.location.len()) as u64
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Executes decoded [`Op`]s against a pickle stack machine without running
+/// any python code.
 #[derive(Debug)]
 pub struct Interpreter {
     globals: HashMap<Global, Value>,
     stack: Vec<Value>,
-    metastack: Vec<Vec<Value>>,
-    memo: HashMap<u32, Value>,
+    /// Footprints of the values in `stack`, index for index.
+    footprints: Vec<Footprint>,
+    metastack: Vec<(Vec<Value>, Vec<Footprint>)>,
+    /// Number of values held in `metastack`, counted towards the stack depth.
+    metastack_len: usize,
+    memo: HashMap<u32, (Value, Footprint)>,
     stop_value: Option<Value>,
+    policy: GlobalPolicy,
+    violations: Vec<Global>,
+    storages: Vec<Storage>,
+    buffers: Option<VecDeque<Vec<u8>>>,
+    limits: InterpreterLimits,
+    op_count: u64,
+    allocated: u64,
+}
+
+/// Bounds on how much work and memory executing a single pickle may use.
+#[derive(Debug, Clone)]
+pub struct InterpreterLimits {
+    /// Ops executed before giving up.
+    pub max_ops: u64,
+    /// Values on the stack, including those set aside by MARK.
+    pub max_stack_depth: usize,
+    /// Nested MARKs.
+    pub max_metastack_depth: usize,
+    pub max_memo_entries: usize,
+    /// Entries in a single list, dict or set.
+    pub max_container_len: usize,
+    /// Approximate bytes copied out of the memo and by DUP.
+    pub max_alloc_bytes: u64,
+    /// Containers nested inside one another. Values are dropped, printed and
+    /// written recursively, so deeper ones could overflow the stack.
+    pub max_nesting_depth: usize,
+}
+
+impl Default for InterpreterLimits {
+    fn default() -> Self {
+        InterpreterLimits {
+            max_ops: 100_000_000,
+            max_stack_depth: 1_000_000,
+            max_metastack_depth: 10_000,
+            max_memo_entries: 10_000_000,
+            max_container_len: 100_000_000,
+            max_alloc_bytes: 2 << 30,
+            max_nesting_depth: 1000,
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_limits(InterpreterLimits::default())
+    }
+
+    pub fn with_limits(limits: InterpreterLimits) -> Self {
         let mut interp = Interpreter {
             globals: HashMap::new(),
             stack: Vec::new(),
+            footprints: Vec::new(),
             metastack: Vec::new(),
+            metastack_len: 0,
             memo: HashMap::new(),
             stop_value: None,
+            policy: GlobalPolicy::default(),
+            violations: Vec::new(),
+            storages: Vec::new(),
+            buffers: None,
+            limits,
+            op_count: 0,
+            allocated: 0,
         };
 
         interp.set_global(
             ORDERED_DICT_NAME.clone(),
-            Function::from_fn("OrderedDict".to_string(), ordered_dict_constructor).into(),
+            Function::from_fn(ORDERED_DICT_NAME.to_string(), ordered_dict_constructor).into(),
         );
 
+        for &(module, name, function) in BUILTIN_FUNCTIONS {
+            interp.set_global(Global::new(module, name), Function::from_fn(format!("{module}.{name}"), function).into());
+        }
+
+        for &(name, rebuild) in tensor::REBUILD_FUNCTIONS {
+            interp.set_global(
+                Global::new("torch._utils", name),
+                Function::from_fn(format!("torch._utils.{name}"), move |_, args| tensor::call_rebuild(name, rebuild, args)).into(),
+            );
+        }
+
         interp
     }
 
+    /// Binds `path` to `value` so GLOBAL ops referencing it resolve to `value`
+    /// instead of an inert [`Value::Global`].
     pub fn set_global(&mut self, path: Global, value: Value) {
         self.globals.insert(path, value);
     }
 
-    fn pop_mark(&mut self) -> Vec<Value> {
-        let mut stack = self.metastack.pop().unwrap();
+    /// Sets the policy GLOBAL, STACK_GLOBAL and INST references are checked
+    /// against. The default policy allows everything.
+    pub fn set_policy(&mut self, policy: GlobalPolicy) {
+        self.policy = policy;
+    }
+
+    /// Globals that broke the policy while it was in [`PolicyMode::Record`].
+    pub fn violations(&self) -> &[Global] {
+        &self.violations
+    }
+
+    /// Every torch storage resolved from a persistent id, including those the
+    /// result no longer references by name.
+    pub fn storages(&self) -> &[Storage] {
+        &self.storages
+    }
+
+    /// Supplies the out-of-band buffers NEXT_BUFFER takes from, in order.
+    /// Without them, a pickle that refers to out-of-band data fails to load.
+    pub fn set_buffers(&mut self, buffers: impl IntoIterator<Item = Vec<u8>>) {
+        self.buffers = Some(buffers.into_iter().collect());
+    }
+
+    fn push_global(&mut self, module: String, name: String) -> Result<()> {
+        let global = Global {
+            module: Cow::Owned(module),
+            name: Cow::Owned(name),
+        };
+
+        if self.policy.is_violation(&global) {
+            match self.policy.mode {
+                PolicyMode::Abort => return Err(ErrorKind::PolicyViolation(global).into()),
+                PolicyMode::Record => self.violations.push(global.clone()),
+            }
+        }
+
+        if let Some(global_def) = self.globals.get(&global) {
+            self.push(global_def.clone())?;
+        }
+        else {
+            self.push(Value::Global(global))?;
+        }
+
+        Ok(())
+    }
+
+    fn reduce(&mut self, (func, func_footprint): (Value, Footprint), (args, args_footprint): (Value, Footprint)) -> Result<()> {
+        if let Value::Function(func) = func {
+            let res = func.call(self, args)?;
+            self.push(res)?;
+        }
+        else {
+            let value = Value::Reduce(Box::new(func), Box::new(args));
+            let footprint = Footprint::container(&value, [func_footprint, args_footprint]);
+            self.push_sized(value, footprint)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pushes a value measured by walking it, which is cheap for scalars and
+    /// the values built-in functions return.
+    fn push(&mut self, value: Value) -> Result<()> {
+        let footprint = Footprint::of(&value);
+        self.push_sized(value, footprint)
+    }
+
+    fn push_sized(&mut self, value: Value, footprint: Footprint) -> Result<()> {
+        if self.stack.len() + self.metastack_len >= self.limits.max_stack_depth {
+            return Err(Limit::StackDepth.exceeded(self.limits.max_stack_depth));
+        }
+        self.check_depth(footprint)?;
+
+        self.stack.push(value);
+        self.footprints.push(footprint);
+        Ok(())
+    }
+
+    fn check_depth(&self, footprint: Footprint) -> Result<()> {
+        if footprint.depth > self.limits.max_nesting_depth {
+            return Err(Limit::NestingDepth.exceeded(self.limits.max_nesting_depth));
+        }
+        Ok(())
+    }
+
+    /// Accounts for a deep copy of a value against the allocation budget.
+    fn charge_clone(&mut self, footprint: Footprint) -> Result<()> {
+        self.allocated = self.allocated.saturating_add(footprint.size);
+        if self.allocated > self.limits.max_alloc_bytes {
+            return Err(Limit::AllocBytes.exceeded(self.limits.max_alloc_bytes));
+        }
+        Ok(())
+    }
+
+    fn memo_get(&mut self, index: u32) -> Result<()> {
+        let &(_, footprint) = self.memo.get(&index).ok_or(ErrorKind::MemoMiss(index))?;
+        self.charge_clone(footprint)?;
+
+        let value = self.memo[&index].0.clone();
+        self.push_sized(value, footprint)
+    }
+
+    fn memo_put(&mut self, index: u32) -> Result<()> {
+        if self.memo.len() >= self.limits.max_memo_entries && !self.memo.contains_key(&index) {
+            return Err(Limit::MemoEntries.exceeded(self.limits.max_memo_entries));
+        }
+
+        let footprint = *self.footprints.last().ok_or(ErrorKind::StackUnderflow)?;
+        self.charge_clone(footprint)?;
+        let value = self.top()?.clone();
+        self.memo.insert(index, (value, footprint));
+        Ok(())
+    }
+
+    /// Records that `items` were put into the container on top of the stack
+    /// and checks it against the limits.
+    fn grow_top(&mut self, items: impl IntoIterator<Item = Footprint>) -> Result<()> {
+        let footprint = self.footprints.last_mut().ok_or(ErrorKind::StackUnderflow)?;
+        footprint.add(items);
+        let footprint = *footprint;
+        self.check_depth(footprint)?;
+
+        let len = match self.top()? {
+            Value::Dict(d) => d.len(),
+            Value::OrderedDict(d) => d.len(),
+            Value::List(items) => items.len(),
+            Value::Set(set) => set.len(),
+            _ => 0,
+        };
+
+        if len > self.limits.max_container_len {
+            return Err(Limit::ContainerLen.exceeded(self.limits.max_container_len));
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Value> {
+        Ok(self.pop_sized()?.0)
+    }
+
+    fn pop_sized(&mut self) -> Result<(Value, Footprint)> {
+        let value = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+        let footprint = self.footprints.pop().unwrap_or_default();
+        Ok((value, footprint))
+    }
+
+    fn top(&self) -> Result<&Value> {
+        Ok(self.stack.last().ok_or(ErrorKind::StackUnderflow)?)
+    }
+
+    fn top_mut(&mut self) -> Result<&mut Value> {
+        Ok(self.stack.last_mut().ok_or(ErrorKind::StackUnderflow)?)
+    }
+
+    fn pop_mark(&mut self) -> Result<(Vec<Value>, Vec<Footprint>)> {
+        let (mut stack, mut footprints) = self.metastack.pop().ok_or(ErrorKind::MissingMark)?;
+        self.metastack_len -= stack.len();
         mem::swap(&mut stack, &mut self.stack);
-        stack
+        mem::swap(&mut footprints, &mut self.footprints);
+        Ok((stack, footprints))
     }
 
+    /// Pushes a tuple, list or frozenset of the values since the last MARK.
+    fn push_marked(&mut self, container: fn(Vec<Value>) -> Value) -> Result<()> {
+        let (items, footprints) = self.pop_mark()?;
+        let value = container(items);
+        let footprint = Footprint::container(&value, footprints);
+        self.push_sized(value, footprint)
+    }
+
+    /// Executes a single op, returning `true` once STOP has been reached.
+    ///
+    /// An op does not know where it was read from, so errors come back
+    /// without an offset. Add one with [`Error::at`](crate::Error::at) from
+    /// [`PickleReader::op_offset`](crate::PickleReader::op_offset), as
+    /// [`load`](crate::load) does.
     pub fn exec_op(&mut self, op: Op) -> Result<bool> {
+        self.op_count += 1;
+        if self.op_count > self.limits.max_ops {
+            return Err(Limit::Ops.exceeded(self.limits.max_ops));
+        }
+
         match op {
-            Op::Proto(_) => {}
+            Op::Proto(version) => {
+                if version > 5 {
+                    return Err(ErrorKind::Unsupported(format!("pickle protocol {version}")).into());
+                }
+            }
             Op::EmptyDict => {
-                self.stack.push(Value::Dict(Dict::default()));
+                self.push(Value::Dict(Dict::default()))?;
             }
             Op::EmptyList => {
-                self.stack.push(Value::List(Vec::default()));
+                self.push(Value::List(Vec::default()))?;
             }
-            Op::BInput(index) => {
-                self.memo
-                    .insert(index as u32, self.stack.last().unwrap().clone());
-            }
-            Op::LongBInput(index) => {
-                self.memo
-                    .insert(index as u32, self.stack.last().unwrap().clone());
+            Op::BInput(index) => self.memo_put(u32::from(index))?,
+            Op::Put(index) | Op::LongBInput(index) => self.memo_put(index)?,
+            Op::Binunicode(s) => self.push(s.into())?,
+            Op::ShortBinunicode(s) => self.push(s.into())?,
+            Op::Binunicode8(s) => self.push(s.into())?,
+            Op::Unicode(s) => self.push(s.into())?,
+            Op::String(data) | Op::BinString(data) | Op::ShortBinString(data) => {
+                // Python 2 `str` is a byte string, and numpy and sklearn keep
+                // raw binary data in it, so only text comes back as a string.
+                match String::from_utf8(data) {
+                    Ok(s) => self.push(s.into())?,
+                    Err(err) => self.push(Value::Bytes(err.into_bytes()))?,
+                }
             }
-            Op::Binunicode(s) => self.stack.push(s.into()),
-            Op::Global(module, name) => {
-                let global = Global {
-                    module: Cow::Owned(module),
-                    name: Cow::Owned(name),
-                };
+            Op::Global(module, name) => self.push_global(module, name)?,
+            Op::StackGlobal => {
+                let name = self.pop()?;
+                let module = self.pop()?;
 
-                if let Some(global_def) = self.globals.get(&global) {
-                    self.stack.push(global_def.clone());
+                match (module, name) {
+                    (Value::String(module), Value::String(name)) => self.push_global(module, name)?,
+                    _ => return Err(ErrorKind::TypeMismatch("STACK_GLOBAL requires str".to_string()).into()),
+                }
+            }
+            Op::Memoize => self.memo_put(self.memo.len() as u32)?,
+            Op::Pop => {
+                if self.stack.is_empty() {
+                    self.pop_mark()?;
                 }
                 else {
-                    self.stack.push(Value::Global(global));
+                    self.pop_sized()?;
                 }
             }
-            Op::BinInt(value) => self.stack.push(value.into()),
-            Op::BinInt1(value) => self.stack.push(value.into()),
-            Op::BinInt2(value) => self.stack.push(value.into()),
-            Op::BinGet(u8_index) => {
-                let index = u8_index as u32;
-                let value = self
-                    .memo
-                    .get(&index)
-                    .ok_or(eyre!("Memo value not found at index {index}"))?
-                    .clone();
-                self.stack.push(value);
-            }
-            Op::LongBinGet(index) => {
-                let value = self
-                    .memo
-                    .get(&index)
-                    .ok_or(eyre!("Memo value not found at index {index}"))?
-                    .clone();
-                self.stack.push(value)
+            Op::PopMark => {
+                self.pop_mark()?;
+            }
+            Op::Dup => {
+                let footprint = *self.footprints.last().ok_or(ErrorKind::StackUnderflow)?;
+                self.charge_clone(footprint)?;
+                let last = self.top()?.clone();
+                self.push_sized(last, footprint)?;
+            }
+            Op::None => self.push(Value::None)?,
+            Op::Float(value) | Op::BinFloat(value) => self.push(value.into())?,
+            Op::BinBytes(data) | Op::ShortBinBytes(data) | Op::BinBytes8(data) => {
+                self.push(Value::Bytes(data))?;
             }
+            Op::ByteArray8(data) => self.push(Value::ByteArray(data))?,
+            Op::Int(value) => self.push(value.into())?,
+            Op::Long(value) | Op::Long1(value) | Op::Long4(value) => self.push(value.into())?,
+            Op::BinInt(value) => self.push(value.into())?,
+            Op::BinInt1(value) => self.push(value.into())?,
+            Op::BinInt2(value) => self.push(value.into())?,
+            Op::BinGet(index) => self.memo_get(u32::from(index))?,
+            Op::Get(index) | Op::LongBinGet(index) => self.memo_get(index)?,
             Op::Mark => {
+                if self.metastack.len() >= self.limits.max_metastack_depth {
+                    return Err(Limit::MetastackDepth.exceeded(self.limits.max_metastack_depth));
+                }
+
                 let old_stack = mem::take(&mut self.stack);
+                let old_footprints = mem::take(&mut self.footprints);
 
-                self.metastack.push(old_stack);
-            }
-            Op::Tuple => {
-                let items = self.pop_mark();
-                //println!("Tuple: {:?}", items);
-                self.stack.push(Value::Tuple(items));
+                self.metastack_len += old_stack.len();
+                self.metastack.push((old_stack, old_footprints));
             }
+            Op::Tuple => self.push_marked(Value::Tuple)?,
             Op::TupleN(u8_n) => {
                 let n = u8_n as usize;
-                let tuple: Vec<Value> = self.stack.drain(self.stack.len() - n..).collect();
-                assert_eq!(tuple.len(), n);
-                self.stack.push(Value::Tuple(tuple))
+                let start = self.stack.len().checked_sub(n).ok_or(ErrorKind::StackUnderflow)?;
+                let tuple = Value::Tuple(self.stack.drain(start..).collect());
+                let footprint = Footprint::container(&tuple, self.footprints.drain(start..));
+                self.push_sized(tuple, footprint)?;
+            }
+            Op::PersId(pid) => {
+                self.push(Value::PersistentLoad(Box::new(pid.into())))?;
             }
             Op::BinPersId => {
-                let pid = self.stack.pop().unwrap();
-                self.stack.push(Value::PersistentLoad(Box::new(pid)));
-            }
-            Op::True => self.stack.push(true.into()),
-            Op::False => self.stack.push(false.into()),
-            Op::Reduce => {
-                let args = self.stack.pop().unwrap();
-                let func = self.stack.pop().unwrap();
-
-                if let Value::Function(func) = func {
-                    let res = func.call(self, args)?;
-                    self.stack.push(res);
+                let (pid, pid_footprint) = self.pop_sized()?;
+                match Storage::from_pid(&pid) {
+                    Some(storage) => {
+                        self.storages.push(storage.clone());
+                        self.push(Value::Storage(storage))?;
+                    }
+                    None => {
+                        let value = Value::PersistentLoad(Box::new(pid));
+                        let footprint = Footprint::container(&value, [pid_footprint]);
+                        self.push_sized(value, footprint)?;
+                    }
                 }
-                else {
-                    self.stack
-                        .push(Value::Reduce(Box::new(func), Box::new(args)));
+            }
+            Op::True => self.push(true.into())?,
+            Op::False => self.push(false.into())?,
+            Op::Reduce | Op::NewObj => {
+                let args = self.pop_sized()?;
+                let func = self.pop_sized()?;
+
+                self.reduce(func, args)?;
+            }
+            Op::NewObjEx => {
+                let (kwargs, kwargs_footprint) = self.pop_sized()?;
+                let args = self.pop_sized()?;
+                let cls = self.pop_sized()?;
+
+                match kwargs {
+                    Value::Dict(ref dict) if dict.is_empty() => self.reduce(cls, args)?,
+                    Value::Dict(_) => {
+                        let (cls, cls_footprint) = cls;
+                        let (args, args_footprint) = args;
+                        let args = Value::Tuple(vec![cls, args, kwargs]);
+                        let footprint = Footprint::container(&args, [cls_footprint, args_footprint, kwargs_footprint]);
+                        let func = Value::Global(NEWOBJ_EX_NAME.clone());
+                        let func_footprint = Footprint::of(&func);
+                        self.reduce((func, func_footprint), (args, footprint))?;
+                    }
+                    _ => return Err(ErrorKind::TypeMismatch("NEWOBJ_EX requires a dict of keyword arguments".to_string()).into()),
                 }
             }
+            Op::Inst(module, name) => {
+                let (args, footprints) = self.pop_mark()?;
+                self.push_global(module, name)?;
+                let class = self.pop_sized()?;
+
+                let args = Value::Tuple(args);
+                let footprint = Footprint::container(&args, footprints);
+                self.reduce(class, (args, footprint))?;
+            }
+            Op::Obj => {
+                let (mut args, mut footprints) = self.pop_mark()?;
+                if args.is_empty() {
+                    return Err(ErrorKind::StackUnderflow.into());
+                }
+                let class = (args.remove(0), footprints.remove(0));
+
+                let args = Value::Tuple(args);
+                let footprint = Footprint::container(&args, footprints);
+                self.reduce(class, (args, footprint))?;
+            }
             Op::SetItem => {
-                let value = self.stack.pop().unwrap();
-                let key = self.stack.pop().unwrap();
-                let last = self
-                    .stack
-                    .last_mut()
-                    .ok_or(eyre!("Expected non-empty stack"))?;
+                let (value, value_footprint) = self.pop_sized()?;
+                let (key, key_footprint) = self.pop_sized()?;
+                let last = self.top_mut()?;
 
                 last.set_item(key, value)?;
+                self.grow_top([key_footprint, value_footprint])?;
             }
             Op::SetItems => {
-                let items = self.pop_mark();
+                let (items, footprints) = self.pop_mark()?;
 
-                if items.len() % 2 != 0 {
-                    return Err(eyre!("SetItems must be an even number of values on stack"));
+                if !items.len().is_multiple_of(2) {
+                    return Err(ErrorKind::TypeMismatch("SETITEMS requires an even number of values".to_string()).into());
                 }
 
-                let last = self
-                    .stack
-                    .last_mut()
-                    .ok_or(eyre!("Expected non-empty stack"))?;
+                let last = self.top_mut()?;
 
                 last.set_items(items.into_iter().tuples())?;
+                self.grow_top(footprints)?;
+            }
+            Op::Dict => {
+                let (items, footprints) = self.pop_mark()?;
+
+                if !items.len().is_multiple_of(2) {
+                    return Err(ErrorKind::TypeMismatch("DICT requires an even number of values".to_string()).into());
+                }
+
+                let mut dict = Value::Dict(Dict::default());
+                dict.set_items(items.into_iter().tuples())?;
+                let footprint = Footprint::container(&dict, footprints);
+                self.push_sized(dict, footprint)?;
+            }
+            Op::List => self.push_marked(Value::List)?,
+            Op::EmptySet => {
+                self.push(Value::Set(Set::default()))?;
+            }
+            Op::AddItems => {
+                let (items, footprints) = self.pop_mark()?;
+
+                let last = self.top_mut()?;
+
+                last.add_items(items.into_iter())?;
+                self.grow_top(footprints)?;
+            }
+            Op::FrozenSet => self.push_marked(|items| Value::FrozenSet(items.into()))?,
+            Op::Append => {
+                let (value, footprint) = self.pop_sized()?;
+                let list = self.top_mut()?;
+                list.extend(vec![value])?;
+                self.grow_top([footprint])?;
             }
             Op::Appends => {
-                let items = self.pop_mark();
-                let list = self.stack.last_mut().unwrap();
+                let (items, footprints) = self.pop_mark()?;
+                let list = self.top_mut()?;
                 list.extend(items)?;
+                self.grow_top(footprints)?;
             }
             Op::Build => {
-                let state = self.stack.pop().unwrap();
-                let last = self.stack.pop().unwrap();
-                self.stack.push(Value::SetState(Box::new(last), Box::new(state)));
+                let (state, state_footprint) = self.pop_sized()?;
+                let (last, last_footprint) = self.pop_sized()?;
+                let value = Value::SetState(Box::new(last), Box::new(state));
+                let footprint = Footprint::container(&value, [last_footprint, state_footprint]);
+                self.push_sized(value, footprint)?;
+            }
+            Op::Ext1(code) => return Err(ErrorKind::Unsupported(format!("extension code {code}")).into()),
+            Op::Ext2(code) => return Err(ErrorKind::Unsupported(format!("extension code {code}")).into()),
+            Op::Ext4(code) => return Err(ErrorKind::Unsupported(format!("extension code {code}")).into()),
+            Op::NextBuffer => {
+                let buffers = self.buffers.as_mut().ok_or_else(|| {
+                    ErrorKind::InvalidArgument("pickle refers to out-of-band data but no buffers were supplied".to_string())
+                })?;
+                let buffer = buffers
+                    .pop_front()
+                    .ok_or_else(|| ErrorKind::InvalidArgument("not enough out-of-band buffers".to_string()))?;
+                self.push(Value::ByteArray(buffer))?;
             }
+            Op::ReadonlyBuffer => match self.top_mut()? {
+                Value::ByteArray(buffer) => {
+                    let buffer = mem::take(buffer);
+                    *self.top_mut()? = Value::Bytes(buffer);
+                }
+                Value::Bytes(_) => {}
+                _ => return Err(ErrorKind::TypeMismatch("READONLY_BUFFER requires a buffer".to_string()).into()),
+            },
+            Op::Frame(_) => {}
             Op::Stop => {
-                let val = self.stack.pop().ok_or(eyre!("Expected non-empty stack"))?;
+                let val = self.pop()?;
                 self.stop_value = Some(val);
             }
         }
@@ -385,7 +1374,39 @@ impl Interpreter {
         Ok(self.stop_value.is_some())
     }
 
+    /// The values pushed since the innermost MARK, or since the start if
+    /// there is none.
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    /// Number of values on the stack, including those set aside by MARK but
+    /// not the marks themselves.
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len() + self.metastack_len
+    }
+
+    /// Returns the value passed to STOP, if it has been reached.
     pub fn into_stop_value(self) -> Option<Value> {
         self.stop_value
     }
+
+    /// Takes the value passed to STOP, if it has been reached.
+    pub fn take_stop_value(&mut self) -> Option<Value> {
+        self.stop_value.take()
+    }
+
+    /// Forgets the stack, memo and stop value so the next pickle in a stream
+    /// can be executed, and starts the op and allocation limits over. Globals,
+    /// the policy, recorded violations and resolved storages are kept.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.footprints.clear();
+        self.metastack.clear();
+        self.metastack_len = 0;
+        self.memo.clear();
+        self.stop_value = None;
+        self.op_count = 0;
+        self.allocated = 0;
+    }
 }
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Seek, SeekFrom};
+use std::ops::Range;
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use num_bigint::BigInt;
+
+use crate::error::{ErrorKind, Result};
+use crate::interpreter::Value;
+use crate::tensor::Storage;
+
+/// The pickled magic number a legacy `torch.save` file starts with,
+/// `0x1950a86a20f9469cfc6c` as a protocol 2 LONG1.
+const MAGIC_PICKLE: &[u8] = b"\x80\x02\x8a\x0a\x6c\xfc\x9c\x46\xf9\x20\x6a\xa8\x50\x19.";
+
+const PROTOCOL_VERSION: i64 = 1001;
+
+/// Whether `prefix`, the first bytes of a file, look like a checkpoint
+/// written by `torch.save` before PyTorch 1.6 switched to zip files.
+pub fn is_legacy_checkpoint(prefix: &[u8]) -> bool {
+    prefix.starts_with(MAGIC_PICKLE)
+}
+
+/// A checkpoint in the legacy `torch.save` format.
+///
+/// The file is five pickles, the magic number, the format version, a dict
+/// describing the saving machine, the object itself and the sorted list of
+/// storage keys, followed by each storage in that order as an 8 byte element
+/// count and the raw data.
+#[derive(Debug)]
+pub struct LegacyCheckpoint {
+    /// The object passed to `torch.save`.
+    pub value: Value,
+    /// Whether storages were written by a little-endian machine.
+    pub little_endian: bool,
+    storages: HashMap<String, Range<u64>>,
+}
+
+impl LegacyCheckpoint {
+    /// Depickles the checkpoint starting at the current position of `r` and
+    /// locates its storages, leaving `r` positioned after the last one.
+    pub fn read<R: BufRead + Seek>(r: &mut R) -> Result<Self> {
+        let start = r.stream_position()?;
+        let file_len = r.seek(SeekFrom::End(0))?;
+        r.seek(SeekFrom::Start(start))?;
+
+        // The storages follow the pickles, so only take as many as we need.
+        let mut pickles = crate::load_all(&mut *r);
+        let mut next_pickle = |what: &str| -> Result<Value> {
+            pickles.next().unwrap_or_else(|| {
+                Err(ErrorKind::InvalidArgument(format!("legacy checkpoint ends before its {}", what)).into())
+            })
+        };
+
+        let magic = next_pickle("magic number")?;
+        if magic.as_bigint() != Some(BigInt::from(0x1950a86a20f9469cfc6c_u128)) {
+            return Err(ErrorKind::InvalidArgument("not a legacy torch checkpoint".to_string()).into());
+        }
+
+        let protocol_version = next_pickle("format version")?;
+        if protocol_version.as_i64() != Some(PROTOCOL_VERSION) {
+            return Err(ErrorKind::Unsupported(format!("legacy checkpoint version {:?}", protocol_version)).into());
+        }
+
+        let sys_info = next_pickle("sys info")?;
+        let little_endian = sys_info
+            .as_dict()
+            .and_then(|info| info.get("little_endian"))
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+
+        let value = next_pickle("object")?;
+        let keys = next_pickle("storage keys")?;
+        let keys = keys
+            .as_list()
+            .ok_or_else(|| ErrorKind::TypeMismatch("legacy storage keys are not a list".to_string()))?;
+
+        // Storages are only written with their element count, so their sizes
+        // come from the dtypes seen while depickling, including storages
+        // inside objects torch rebuilds itself.
+        let element_sizes: HashMap<String, u64> = pickles
+            .interpreter()
+            .storages()
+            .iter()
+            .map(|storage: &Storage| (storage.key.clone(), storage.dtype.size() as u64))
+            .collect();
+        drop(pickles);
+
+        let mut storages = HashMap::new();
+        let mut pos = r.stream_position()?;
+
+        for key in keys.iter() {
+            let key = key
+                .as_str()
+                .ok_or_else(|| ErrorKind::TypeMismatch("legacy storage key is not a string".to_string()))?;
+            let element_size = *element_sizes
+                .get(key)
+                .ok_or_else(|| ErrorKind::Unsupported(format!("storage {} has no known dtype", key)))?;
+
+            let numel = if little_endian {
+                r.read_u64::<LittleEndian>()?
+            }
+            else {
+                r.read_u64::<BigEndian>()?
+            };
+            let start = pos + 8;
+            let end = numel
+                .checked_mul(element_size)
+                .and_then(|len| start.checked_add(len))
+                .filter(|&end| end <= file_len)
+                .ok_or(ErrorKind::Truncated)?;
+
+            storages.insert(key.to_string(), start..end);
+            pos = r.seek(SeekFrom::Start(end))?;
+        }
+
+        Ok(LegacyCheckpoint {
+            value,
+            little_endian,
+            storages,
+        })
+    }
+
+    /// Byte range of a storage's data within the file.
+    pub fn storage_range(&self, key: &str) -> Option<Range<u64>> {
+        self.storages.get(key).cloned()
+    }
+}
@@ -0,0 +1,179 @@
+//! dilligent pickle, a cautious depickler.
+//!
+//! Pickles are decoded into [`Op`]s by [`PickleReader`] and executed by an
+//! [`Interpreter`] that never runs python code, producing a [`Value`] tree.
+
+use std::io::{BufRead, Write};
+
+mod ast;
+mod de;
+mod decoder;
+mod dis;
+mod encoder;
+mod error;
+mod interpreter;
+mod legacy;
+mod npy;
+mod opcodes;
+mod policy;
+mod safetensors;
+mod sanitize;
+mod scan;
+mod ser;
+mod tensor;
+
+pub use crate::ast::Op;
+pub use crate::de::from_value;
+pub use crate::decoder::{DecoderLimits, PickleReader};
+pub use crate::dis::dis;
+pub use crate::encoder::PickleWriter;
+pub use crate::error::{Error, ErrorKind, Limit, Result};
+pub use crate::interpreter::{
+    Dict, Function, Global, Interpreter, InterpreterLimits, OrderedDict, Set, Value,
+};
+pub use crate::legacy::{is_legacy_checkpoint, LegacyCheckpoint};
+pub use crate::npy::{numpy_descr, write_npy};
+pub use crate::opcodes::OpCode;
+pub use crate::policy::{Classification, GlobalPattern, GlobalPolicy, PolicyMode};
+pub use crate::safetensors::{write_safetensors_header, SafetensorsTensor};
+pub use crate::sanitize::sanitize;
+pub use crate::scan::sarif::sarif_log;
+pub use crate::scan::{Finding, FindingKind, MemberReport, ScanReport, Scanner, Severity};
+pub use crate::ser::{to_value, to_vec};
+pub use crate::tensor::{named_leaves, named_tensors, DType, Storage, Tensor};
+
+/// Depickles the first pickle in `pickle_file`, returning the value passed to
+/// STOP or `None` if the stream ended before it.
+pub fn load<R: BufRead>(pickle_file: R) -> Result<Option<Value>> {
+    load_with_limits(pickle_file, DecoderLimits::default(), InterpreterLimits::default())
+}
+
+/// Like [`load`], with the resource limits decoding and interpreting the
+/// pickle may use.
+pub fn load_with_limits<R: BufRead>(
+    pickle_file: R,
+    decoder_limits: DecoderLimits,
+    interpreter_limits: InterpreterLimits,
+) -> Result<Option<Value>> {
+    let mut reader = PickleReader::with_limits(pickle_file, decoder_limits);
+    let mut interp = Interpreter::with_limits(interpreter_limits);
+
+    while let Some(op) = reader.next().transpose()? {
+        let stopped = interp
+            .exec_op(op)
+            .map_err(|err| err.at(reader.op_offset(), reader.op_code()))?;
+
+        if stopped {
+            break;
+        }
+    }
+
+    Ok(interp.into_stop_value())
+}
+
+/// Whether `prefix`, the first bytes of a file, look like a pickle.
+///
+/// Protocol 2 and later pickles start with PROTO. Older ones may start with
+/// any op, so they have to decode and run without error until STOP or the
+/// end of `prefix`, which text and most other files do not.
+pub fn is_pickle(prefix: &[u8]) -> bool {
+    match prefix {
+        [] | [0x80] => false,
+        [0x80, protocol, ..] => (2..=5).contains(protocol),
+        _ => match load(prefix) {
+            Ok(_) => true,
+            Err(err) => matches!(err.kind(), ErrorKind::Truncated),
+        },
+    }
+}
+
+/// Pickles `value` at `protocol` 2 to 5, see [`PickleWriter`].
+pub fn dump<W: Write>(value: &Value, pickle_file: W, protocol: u8) -> Result<()> {
+    PickleWriter::new(pickle_file, protocol)?.dump(value)
+}
+
+/// Depickles every pickle written back to back in `pickle_file`, as
+/// `torch.save` and repeated `pickle.dump` calls do.
+///
+/// Each pickle starts with an empty stack and memo. Input after the last
+/// pickle whose first op does not decode is reported as
+/// [`ErrorKind::TrailingData`], a later pickle that breaks further in with
+/// its own error. Iteration ends after the first error.
+pub fn load_all<R: BufRead>(pickle_file: R) -> Pickles<R> {
+    Pickles {
+        reader: PickleReader::new(pickle_file),
+        interp: Interpreter::new(),
+        count: 0,
+        done: false,
+    }
+}
+
+/// Iterator over the pickles in a stream, see [`load_all`].
+pub struct Pickles<R: BufRead> {
+    reader: PickleReader<R>,
+    interp: Interpreter,
+    count: usize,
+    done: bool,
+}
+
+impl <R: BufRead> Pickles<R> {
+    /// Byte offset just past the last pickle returned.
+    pub fn position(&self) -> u64 {
+        self.reader.position()
+    }
+
+    /// The interpreter the pickles are executed by.
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interp
+    }
+
+    fn next_value(&mut self) -> Result<Option<Value>> {
+        if self.count > 0 {
+            self.reader.next_pickle();
+            self.interp.reset();
+        }
+
+        if self.reader.at_eof()? {
+            return Ok(None);
+        }
+
+        // Input after a pickle that does not even start like one is trailing
+        // data, a later pickle broken further in is reported as it is.
+        let start = self.reader.position();
+        let mut trailing = self.count > 0;
+
+        loop {
+            let op = match self.reader.next().transpose() {
+                Ok(Some(op)) => op,
+                Ok(None) => return Err(Error::from(ErrorKind::Truncated).at(self.reader.position(), None)),
+                Err(err) if trailing => return Err(err.into_trailing_data(start)),
+                Err(err) => return Err(err),
+            };
+            trailing = false;
+
+            let stopped = self
+                .interp
+                .exec_op(op)
+                .map_err(|err| err.at(self.reader.op_offset(), self.reader.op_code()))?;
+
+            if stopped {
+                self.count += 1;
+                return Ok(self.interp.take_stop_value());
+            }
+        }
+    }
+}
+
+impl <R: BufRead> Iterator for Pickles<R> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.next_value().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
@@ -1,54 +1,167 @@
-use byteorder::{LittleEndian, ReadBytesExt};
-use clap::Parser;
-use eyre::{eyre, Result};
-use std::io::{BufRead, BufReader, Read};
-use std::path::PathBuf;
-use std::{fs, io};
-
-mod ast;
-mod decoder;
-mod interpreter;
-mod opcodes;
-
-use opcodes::OpCode;
-
-use crate::ast::Op;
-use crate::decoder::PickleReader;
-use crate::interpreter::Interpreter;
+use clap::{Parser, Subcommand, ValueEnum};
+use dilligent::{
+    GlobalPolicy, LegacyCheckpoint, MemberReport, PickleWriter, PolicyMode, SafetensorsTensor, Scanner, Severity,
+    Tensor, Value,
+};
+use eyre::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 struct Args {
-    /// Model file to load
-    model_file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Model file to dump, as `dump` does, for scripts written before the
+    /// subcommands
+    model_file: Option<PathBuf>,
 }
 
-fn dump_pickle(r: &mut dyn Read) -> Result<()> {
-    let pickle_file: BufReader<_> = BufReader::new(r);
-    let reader = PickleReader::new(pickle_file);
-    let mut interp = Interpreter::new();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the value of every pickle in a model file or bare pickle
+    Dump {
+        /// Model file or pickle to load, `-` for stdin
+        model_file: PathBuf,
+    },
+    /// Disassemble every pickle in a model file, like pickletools.dis
+    Dis {
+        /// Model file or pickle to read, `-` for stdin
+        model_file: PathBuf,
+        /// Also print the stack depth after each op
+        #[arg(long)]
+        stack_depth: bool,
+    },
+    /// List every tensor in a checkpoint's state_dict
+    Tensors {
+        /// Model file or pickle to load, `-` for stdin
+        model_file: PathBuf,
+    },
+    /// Convert a checkpoint's state_dict to another format
+    Convert {
+        /// Format to write
+        #[arg(long, value_enum)]
+        to: ConvertFormat,
+        /// Model file or pickle to read, `-` for stdin
+        model_file: PathBuf,
+        /// File to write
+        output_file: PathBuf,
+    },
+    /// Write tensors to a .npy file, or a whole state_dict to a .npz file
+    Extract {
+        /// Model file or pickle to read, `-` for stdin
+        model_file: PathBuf,
+        /// `.npy` or `.npz` file to write
+        output_file: PathBuf,
+        /// Dotted key of a tensor to write, all of them if not given
+        #[arg(short, long = "tensor")]
+        tensors: Vec<String>,
+    },
+    /// Rewrite a checkpoint so it only references globals torch's
+    /// weights_only loader allows
+    ///
+    /// Calls to anything else are replaced with None and listed, and tensor
+    /// data is copied through unchanged.
+    Sanitize {
+        /// Model file or pickle to read, `-` for stdin
+        model_file: PathBuf,
+        /// File to write
+        output_file: PathBuf,
+        /// Fail on the first disallowed global instead of replacing it
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Flag pickles that reference dangerous callables
+    ///
+    /// Exits with 0 when every pickle is safe, 2 when something is
+    /// suspicious and 3 when something is malicious.
+    Scan {
+        /// Model files or pickles to scan, `-` for stdin
+        #[arg(required = true)]
+        model_files: Vec<PathBuf>,
 
-    for maybe_op in reader {
-        let op = maybe_op?;
-        //println!("{:?}", op);
-        if interp.exec_op(op)? {
-            break;
-        }
-    }
+        /// How to print findings
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+}
 
-    if let Some(value) = interp.into_stop_value() {
-        println!("{:#?}", value);
-    }
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// One line per finding followed by the verdict
+    Text,
+    /// One JSON object per finding
+    JsonLines,
+    /// A SARIF 2.1.0 log
+    Sarif,
+}
 
-    Ok(())
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ConvertFormat {
+    Safetensors,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+type Input = Box<dyn ReadSeek>;
+
+/// How a model file is laid out, going by its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModelFormat {
+    /// A zip archive of pickles and storages, as written by `torch.save`
+    /// since PyTorch 1.6.
+    Zip,
+    /// Concatenated pickles followed by raw storages, as written by older
+    /// versions of `torch.save`.
+    Legacy,
+    /// A single pickle, as written by `pickle.dump`.
+    Pickle,
+}
+
+/// Bytes looked at to tell formats apart, enough for a few protocol 0 ops.
+const DETECT_PREFIX_LEN: u64 = 1024;
+
+/// Opens a model file, or reads all of stdin when it is `-`, and works out
+/// its format.
+fn open_input(model_file: &Path) -> Result<(Input, ModelFormat)> {
+    let mut input: Input = if model_file == Path::new("-") {
+        // Zip and legacy files need seeking, which stdin can't do.
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        Box::new(Cursor::new(bytes))
+    }
+    else {
+        Box::new(fs::File::open(model_file)?)
+    };
+
+    let mut prefix = Vec::new();
+    (&mut input).take(DETECT_PREFIX_LEN).read_to_end(&mut prefix)?;
+    input.rewind()?;
 
-    let file = fs::File::open(args.model_file)?;
+    let format = if prefix.starts_with(b"PK\x03\x04") || prefix.starts_with(b"PK\x05\x06") {
+        ModelFormat::Zip
+    }
+    else if dilligent::is_legacy_checkpoint(&prefix) {
+        ModelFormat::Legacy
+    }
+    else if dilligent::is_pickle(&prefix) {
+        ModelFormat::Pickle
+    }
+    else {
+        eyre::bail!("{} is not a zip, legacy torch checkpoint or pickle", model_file.display())
+    };
+
+    Ok((input, format))
+}
 
-    let mut zip_file = zip::ZipArchive::new(file)?;
+fn open_model(input: Input) -> Result<(zip::ZipArchive<Input>, Vec<String>)> {
+    let zip_file = zip::ZipArchive::new(input)?;
 
     let pickle_filenames: Vec<String> = zip_file
         .file_names()
@@ -56,6 +169,48 @@ fn main() -> Result<()> {
         .map(|s| s.to_string())
         .collect();
 
+    Ok((zip_file, pickle_filenames))
+}
+
+fn open_legacy(input: Input) -> Result<(BufReader<Input>, LegacyCheckpoint)> {
+    let mut input = BufReader::new(input);
+    let checkpoint = LegacyCheckpoint::read(&mut input)?;
+
+    Ok((input, checkpoint))
+}
+
+fn dump_pickle(r: &mut dyn Read) -> Result<()> {
+    let pickle_file: BufReader<_> = BufReader::new(r);
+    let mut pickles = dilligent::load_all(pickle_file).enumerate().peekable();
+
+    while let Some((i, value)) = pickles.next() {
+        let value = value?;
+        // Only number the pickles when there is more than one.
+        if i > 0 || pickles.peek().is_some() {
+            println!("Pickle {}:", i);
+        }
+        println!("{:#?}", value);
+    }
+
+    Ok(())
+}
+
+fn dump(model_file: &Path) -> Result<()> {
+    let (mut input, format) = open_input(model_file)?;
+
+    match format {
+        ModelFormat::Zip => {}
+        ModelFormat::Legacy => {
+            let (_, checkpoint) = open_legacy(input)?;
+            println!("Found legacy checkpoint");
+            println!("{:#?}", checkpoint.value);
+            return Ok(());
+        }
+        ModelFormat::Pickle => return dump_pickle(&mut input),
+    }
+
+    let (mut zip_file, pickle_filenames) = open_model(input)?;
+
     for name in pickle_filenames.into_iter() {
         println!("Found pkl: {:?}", name);
         let mut f = zip_file.by_name(&name)?;
@@ -64,3 +219,599 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Disassembles pickles from `r` until `count` of them have been listed, or
+/// until the end of the input if not given.
+fn dis_pickles(r: &mut dyn Read, count: Option<usize>, stack_depth: bool) -> Result<()> {
+    let mut reader = dilligent::PickleReader::new(BufReader::new(r));
+    let mut out = io::stdout().lock();
+
+    for i in 0.. {
+        if count == Some(i) || (i > 0 && reader.at_eof()?) {
+            break;
+        }
+        if i > 0 {
+            writeln!(out)?;
+        }
+
+        dilligent::dis(&mut reader, &mut out, stack_depth)?;
+        reader.next_pickle();
+    }
+
+    Ok(())
+}
+
+fn dis(model_file: &Path, stack_depth: bool) -> Result<()> {
+    let (mut input, format) = open_input(model_file)?;
+
+    match format {
+        ModelFormat::Zip => {}
+        // The storages after the pickles are raw data.
+        ModelFormat::Legacy => return dis_pickles(&mut input, Some(LEGACY_PICKLES.len()), stack_depth),
+        ModelFormat::Pickle => return dis_pickles(&mut input, None, stack_depth),
+    }
+
+    let (mut zip_file, pickle_filenames) = open_model(input)?;
+
+    for name in pickle_filenames.into_iter() {
+        println!("Found pkl: {:?}", name);
+        let mut f = zip_file.by_name(&name)?;
+        dis_pickles(&mut f, Some(1), stack_depth)?;
+    }
+
+    Ok(())
+}
+
+fn tensors(model_file: &Path) -> Result<()> {
+    let (checkpoint, value) = Checkpoint::open(model_file)?;
+    let mut rows = Vec::new();
+    let mut total_numel: u64 = 0;
+    let mut total_bytes: u64 = 0;
+
+    for (key, tensor) in dilligent::named_tensors(&value) {
+        let numel = tensor.numel();
+        let bytes = numel.saturating_mul(tensor.dtype().size() as u64);
+        total_numel = total_numel.saturating_add(numel);
+        total_bytes = total_bytes.saturating_add(bytes);
+
+        rows.push([
+            key,
+            tensor.dtype().to_string(),
+            format!("{:?}", tensor.shape),
+            numel.to_string(),
+            bytes.to_string(),
+            checkpoint.storage_name(tensor),
+        ]);
+    }
+
+    let header = ["key", "dtype", "shape", "numel", "bytes", "storage"].map(String::from);
+    let mut widths = [0; 6];
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    println!("total: {} tensors, {} parameters, {} bytes", rows.len(), total_numel, total_bytes);
+
+    Ok(())
+}
+
+/// Whether a checkpoint's storages were saved big-endian, according to its
+/// `<archive>/byteorder` entry. Older checkpoints without one are little-endian.
+fn is_big_endian(zip_file: &mut zip::ZipArchive<Input>, archive: &str) -> Result<bool> {
+    let mut byteorder = String::new();
+
+    match zip_file.by_name(&format!("{}/byteorder", archive)) {
+        Ok(mut f) => {
+            f.read_to_string(&mut byteorder)?;
+        }
+        Err(zip::result::ZipError::FileNotFound) => return Ok(false),
+        Err(err) => return Err(err.into()),
+    }
+
+    Ok(byteorder.trim() == "big")
+}
+
+/// Metadata safetensors can carry: every string, number and bool outside the
+/// tensors themselves.
+fn scalar_metadata(value: &Value) -> BTreeMap<String, String> {
+    dilligent::named_leaves(value)
+        .into_iter()
+        .filter_map(|(key, leaf)| {
+            let text = match leaf {
+                Value::String(s) => s.clone(),
+                Value::Int(i) => i.to_string(),
+                Value::BigInt(i) => i.to_string(),
+                Value::Float(f) => f.to_string(),
+                Value::Bool(b) => if *b { "True" } else { "False" }.to_string(),
+                _ => return None,
+            };
+            Some((key, text))
+        })
+        .collect()
+}
+
+/// Where a checkpoint's storages are read from.
+enum Storages {
+    Zip {
+        zip_file: zip::ZipArchive<Input>,
+        archive: String,
+    },
+    Legacy {
+        file: BufReader<Input>,
+        checkpoint: LegacyCheckpoint,
+    },
+    /// A bare pickle, which can describe tensors but never holds their data.
+    None,
+}
+
+/// The storages of a checkpoint with a single pickled value.
+struct Checkpoint {
+    storages: Storages,
+    big_endian: bool,
+    /// Tensors sharing a storage are usually next to each other, so the last
+    /// storage read is kept around.
+    storage: Option<(String, Vec<u8>)>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint and depickles its value.
+    fn open(model_file: &Path) -> Result<(Self, Value)> {
+        let (input, format) = open_input(model_file)?;
+
+        let (storages, value, big_endian) = match format {
+            ModelFormat::Zip => return Self::open_zip(model_file, input),
+            ModelFormat::Legacy => {
+                let (file, checkpoint) = open_legacy(input)?;
+                let value = checkpoint.value.clone();
+                let big_endian = !checkpoint.little_endian;
+                (Storages::Legacy { file, checkpoint }, value, big_endian)
+            }
+            ModelFormat::Pickle => {
+                let value = dilligent::load(BufReader::new(input))?
+                    .ok_or_else(|| eyre::eyre!("{} has no value", model_file.display()))?;
+                (Storages::None, value, false)
+            }
+        };
+
+        let checkpoint = Checkpoint {
+            storages,
+            big_endian,
+            storage: None,
+        };
+
+        Ok((checkpoint, value))
+    }
+
+    fn open_zip(model_file: &Path, input: Input) -> Result<(Self, Value)> {
+        let (mut zip_file, pickle_filenames) = open_model(input)?;
+
+        let [name] = pickle_filenames.as_slice() else {
+            eyre::bail!("expected exactly one pickle in {}, found {}", model_file.display(), pickle_filenames.len());
+        };
+        let archive = name.strip_suffix("/data.pkl").unwrap_or(name).to_string();
+
+        let value = {
+            let f = zip_file.by_name(name)?;
+            dilligent::load(BufReader::new(f))?.ok_or_else(|| eyre::eyre!("{} has no value", name))?
+        };
+        let big_endian = is_big_endian(&mut zip_file, &archive)?;
+
+        let checkpoint = Checkpoint {
+            storages: Storages::Zip { zip_file, archive },
+            big_endian,
+            storage: None,
+        };
+
+        Ok((checkpoint, value))
+    }
+
+    /// Where the tensor's data lives, for display.
+    fn storage_name(&self, tensor: &Tensor) -> String {
+        match &self.storages {
+            Storages::Zip { archive, .. } => tensor.storage.data_entry(archive),
+            Storages::Legacy { checkpoint, .. } => match checkpoint.storage_range(&tensor.storage.key) {
+                Some(range) => format!("storage {} at {}", tensor.storage.key, range.start),
+                None => format!("storage {}", tensor.storage.key),
+            },
+            Storages::None => format!("storage {} (not saved)", tensor.storage.key),
+        }
+    }
+
+    fn read_storage(&mut self, key: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+
+        match &mut self.storages {
+            Storages::Zip { zip_file, archive } => {
+                let entry = format!("{}/data/{}", archive, key);
+                zip_file.by_name(&entry)?.read_to_end(&mut bytes)?;
+            }
+            Storages::Legacy { file, checkpoint } => {
+                let range = checkpoint
+                    .storage_range(key)
+                    .ok_or_else(|| eyre::eyre!("storage {} is not in the checkpoint", key))?;
+                file.seek(SeekFrom::Start(range.start))?;
+                file.take(range.end - range.start).read_to_end(&mut bytes)?;
+            }
+            Storages::None => eyre::bail!("bare pickles do not hold tensor data"),
+        }
+
+        Ok(bytes)
+    }
+
+    /// The tensor's elements in row-major, little-endian order.
+    fn tensor_bytes(&mut self, tensor: &Tensor) -> Result<Vec<u8>> {
+        let key = &tensor.storage.key;
+
+        let bytes = match &self.storage {
+            Some((cached, bytes)) if cached == key => bytes,
+            _ => {
+                let bytes = self.read_storage(key)?;
+                &self.storage.insert((key.clone(), bytes)).1
+            }
+        };
+
+        Ok(tensor.contiguous_bytes(bytes, self.big_endian)?)
+    }
+}
+
+/// An output file being written, deleted on drop unless kept, so a command
+/// that fails halfway does not leave a partial file behind.
+struct PartialOutput<'a> {
+    path: &'a Path,
+    keep: bool,
+}
+
+impl<'a> PartialOutput<'a> {
+    fn create(path: &'a Path) -> Result<(fs::File, Self)> {
+        let file = fs::File::create(path)?;
+        Ok((file, PartialOutput { path, keep: false }))
+    }
+
+    fn keep(mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for PartialOutput<'_> {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(self.path);
+        }
+    }
+}
+
+fn convert_to_safetensors(model_file: &Path, output_file: &Path) -> Result<()> {
+    let (mut checkpoint, value) = Checkpoint::open(model_file)?;
+
+    let tensors = dilligent::named_tensors(&value);
+    let entries: Vec<SafetensorsTensor> = tensors
+        .iter()
+        .map(|(name, tensor)| SafetensorsTensor {
+            name,
+            dtype: tensor.dtype(),
+            shape: &tensor.shape,
+        })
+        .collect();
+
+    let mut header = Vec::new();
+    dilligent::write_safetensors_header(&mut header, &entries, &scalar_metadata(&value))?;
+
+    let (out_file, output) = PartialOutput::create(output_file)?;
+    let mut out = BufWriter::new(out_file);
+    out.write_all(&header)?;
+
+    for (_, tensor) in tensors.iter() {
+        out.write_all(&checkpoint.tensor_bytes(tensor)?)?;
+    }
+
+    out.flush()?;
+    output.keep();
+
+    Ok(())
+}
+
+fn extract(model_file: &Path, output_file: &Path, selected: &[String]) -> Result<()> {
+    let (mut checkpoint, value) = Checkpoint::open(model_file)?;
+
+    let mut tensors = dilligent::named_tensors(&value);
+    if !selected.is_empty() {
+        if let Some(missing) = selected.iter().find(|name| !tensors.iter().any(|(key, _)| key == *name)) {
+            eyre::bail!("no tensor named {:?} in {}", missing, model_file.display());
+        }
+        tensors.retain(|(key, _)| selected.contains(key));
+    }
+
+    match output_file.extension().and_then(|ext| ext.to_str()) {
+        Some("npy") => {
+            let [(_, tensor)] = tensors.as_slice() else {
+                eyre::bail!("a .npy file holds one tensor, pick one of {} with --tensor", tensors.len());
+            };
+
+            let data = checkpoint.tensor_bytes(tensor)?;
+            let (out_file, output) = PartialOutput::create(output_file)?;
+            let mut out = BufWriter::new(out_file);
+            dilligent::write_npy(&mut out, tensor.dtype(), &tensor.shape, &data)?;
+            out.flush()?;
+            output.keep();
+        }
+        Some("npz") => {
+            let (out_file, output) = PartialOutput::create(output_file)?;
+            let mut npz = zip::ZipWriter::new(out_file);
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored)
+                .large_file(true);
+
+            for (key, tensor) in tensors.iter() {
+                let data = checkpoint.tensor_bytes(tensor)?;
+                npz.start_file(format!("{}.npy", key), options)?;
+                dilligent::write_npy(&mut npz, tensor.dtype(), &tensor.shape, &data)?;
+            }
+
+            npz.finish()?;
+            output.keep();
+        }
+        _ => eyre::bail!("{} should end in .npy or .npz", output_file.display()),
+    }
+
+    Ok(())
+}
+
+/// The protocol `torch.save` writes, which torch's `weights_only` loader
+/// expects.
+const SANITIZE_PROTOCOL: u8 = 2;
+
+/// Sanitizes one depickled value in place, listing what was removed.
+fn sanitize_value(value: &mut Value, policy: &GlobalPolicy, location: &str) -> Result<()> {
+    let removed = dilligent::sanitize(value, policy).map_err(|err| eyre::eyre!("{}: {}", location, err))?;
+
+    for global in removed.iter() {
+        println!("{}: replaced {} with None", location, global);
+    }
+
+    Ok(())
+}
+
+fn sanitize(model_file: &Path, output_file: &Path, strict: bool) -> Result<()> {
+    let mode = if strict { PolicyMode::Abort } else { PolicyMode::Record };
+    let policy = GlobalPolicy::torch_weights_only().mode(mode);
+    let path = model_file.display();
+    let (input, format) = open_input(model_file)?;
+
+    // Everything is depickled and sanitized before the output is created, so
+    // a refused checkpoint leaves nothing behind.
+    match format {
+        ModelFormat::Zip => {
+            let (mut zip_file, pickle_filenames) = open_model(input)?;
+
+            let mut pickles = BTreeMap::new();
+            for name in pickle_filenames.into_iter() {
+                let f = zip_file.by_name(&name)?;
+                let mut value = dilligent::load(BufReader::new(f))?
+                    .ok_or_else(|| eyre::eyre!("{}:{} has no value", path, name))?;
+                sanitize_value(&mut value, &policy, &format!("{}:{}", path, name))?;
+
+                let mut pickle = Vec::new();
+                dilligent::dump(&value, &mut pickle, SANITIZE_PROTOCOL)?;
+                pickles.insert(name, pickle);
+            }
+
+            let (out_file, output) = PartialOutput::create(output_file)?;
+            let mut out = zip::ZipWriter::new(out_file);
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored)
+                .large_file(true);
+
+            for i in 0..zip_file.len() {
+                let mut f = zip_file.by_index(i)?;
+                let name = f.name().to_string();
+
+                // torch aligns records to 64 bytes so storages can be mmapped.
+                out.start_file_aligned(name.as_str(), options, 64)?;
+                match pickles.get(&name) {
+                    Some(pickle) => out.write_all(pickle)?,
+                    None => {
+                        io::copy(&mut f, &mut out)?;
+                    }
+                }
+            }
+
+            out.finish()?;
+            output.keep();
+        }
+        ModelFormat::Legacy => {
+            let mut file = BufReader::new(input);
+            let mut pickles = dilligent::load_all(&mut file);
+            let mut values = pickles.by_ref().take(LEGACY_PICKLES.len()).collect::<dilligent::Result<Vec<_>>>()?;
+            if values.len() < LEGACY_PICKLES.len() {
+                eyre::bail!("{} ends before its {}", path, LEGACY_PICKLES[values.len()]);
+            }
+            let storages_start = pickles.position();
+
+            sanitize_value(&mut values[3], &policy, &format!("{}:{}", path, LEGACY_PICKLES[3]))?;
+
+            let (out_file, output) = PartialOutput::create(output_file)?;
+            let mut out = BufWriter::new(out_file);
+            let mut writer = PickleWriter::new(&mut out, SANITIZE_PROTOCOL)?;
+            for value in values.iter() {
+                writer.dump(value)?;
+            }
+
+            file.seek(SeekFrom::Start(storages_start))?;
+            io::copy(&mut file, &mut out)?;
+            out.flush()?;
+            output.keep();
+        }
+        ModelFormat::Pickle => {
+            let mut values = dilligent::load_all(BufReader::new(input)).collect::<dilligent::Result<Vec<_>>>()?;
+            let several = values.len() > 1;
+            for (i, value) in values.iter_mut().enumerate() {
+                let location = if several { format!("{}:pickle-{}", path, i) } else { path.to_string() };
+                sanitize_value(value, &policy, &location)?;
+            }
+
+            let (out_file, output) = PartialOutput::create(output_file)?;
+            let mut out = BufWriter::new(out_file);
+            let mut writer = PickleWriter::new(&mut out, SANITIZE_PROTOCOL)?;
+            for value in values.iter() {
+                writer.dump(value)?;
+            }
+            out.flush()?;
+            output.keep();
+        }
+    }
+
+    Ok(())
+}
+
+/// What each pickle at the start of a legacy checkpoint holds.
+const LEGACY_PICKLES: [&str; 5] = ["magic_number", "protocol_version", "sys_info", "object", "storage_keys"];
+
+fn scan(model_files: &[PathBuf]) -> Result<Vec<MemberReport>> {
+    let scanner = Scanner::new();
+    let mut reports = Vec::new();
+
+    for path in model_files.iter() {
+        let (input, format) = open_input(path)?;
+
+        match format {
+            ModelFormat::Zip => {
+                let (mut zip_file, pickle_filenames) = open_model(input)?;
+
+                for member in pickle_filenames.into_iter() {
+                    let f = zip_file.by_name(&member)?;
+                    let report = scanner.scan(BufReader::new(f));
+
+                    reports.push(MemberReport {
+                        path: path.clone(),
+                        member: Some(member),
+                        report,
+                    });
+                }
+            }
+            ModelFormat::Legacy => {
+                let mut input = BufReader::new(input);
+
+                for member in LEGACY_PICKLES {
+                    let report = scanner.scan(&mut input);
+                    let failed = report.error.is_some();
+
+                    reports.push(MemberReport {
+                        path: path.clone(),
+                        member: Some(member.to_string()),
+                        report,
+                    });
+
+                    // Once a pickle is unreadable we no longer know where the next starts.
+                    if failed {
+                        break;
+                    }
+                }
+            }
+            ModelFormat::Pickle => {
+                let pickles = scanner.scan_all(BufReader::new(input));
+                let several = pickles.len() > 1;
+
+                for (i, report) in pickles.into_iter().enumerate() {
+                    reports.push(MemberReport {
+                        path: path.clone(),
+                        member: several.then(|| format!("pickle-{}", i)),
+                        report,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(reports)
+}
+
+/// Where a finding is, as `path:member` or just `path` for a bare pickle.
+fn member_location(path: &Path, member: &Option<String>) -> String {
+    match member {
+        Some(member) => format!("{}:{}", path.display(), member),
+        None => path.display().to_string(),
+    }
+}
+
+fn print_text(reports: &[MemberReport], verdict: Severity) {
+    for MemberReport { path, member, report } in reports.iter() {
+        let location = member_location(path, member);
+
+        for finding in report.findings.iter() {
+            println!(
+                "{}:{}: {} {} {} ({:?})",
+                location,
+                finding.offset,
+                finding.severity,
+                finding.kind,
+                finding.global,
+                finding.opcode
+            );
+        }
+
+        if let Some(err) = &report.error {
+            println!("{}: {} error {}", location, Severity::Suspicious, err);
+        }
+    }
+
+    println!("{}", verdict);
+}
+
+fn print_json_lines(reports: &[MemberReport]) {
+    for line in reports.iter().flat_map(MemberReport::json_findings) {
+        println!("{}", line);
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let command = match (args.command, args.model_file) {
+        (Some(command), _) => command,
+        (None, Some(model_file)) => Command::Dump { model_file },
+        (None, None) => eyre::bail!("a command or model file is required"),
+    };
+
+    match command {
+        Command::Dump { model_file } => dump(&model_file),
+        Command::Dis { model_file, stack_depth } => dis(&model_file, stack_depth),
+        Command::Tensors { model_file } => tensors(&model_file),
+        Command::Extract { model_file, output_file, tensors } => extract(&model_file, &output_file, &tensors),
+        Command::Convert { to, model_file, output_file } => match to {
+            ConvertFormat::Safetensors => convert_to_safetensors(&model_file, &output_file),
+        },
+        Command::Sanitize { model_file, output_file, strict } => sanitize(&model_file, &output_file, strict),
+        Command::Scan { model_files, format } => {
+            let reports = scan(&model_files)?;
+            let verdict = reports
+                .iter()
+                .map(|member| member.report.severity())
+                .max()
+                .unwrap_or(Severity::Safe);
+
+            match format {
+                Format::Text => print_text(&reports, verdict),
+                Format::JsonLines => print_json_lines(&reports),
+                Format::Sarif => println!("{:#}", dilligent::sarif_log(&reports)),
+            }
+
+            let code = match verdict {
+                Severity::Safe => 0,
+                Severity::Suspicious => 2,
+                Severity::Malicious => 3,
+            };
+            process::exit(code)
+        }
+    }
+}
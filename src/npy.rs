@@ -0,0 +1,75 @@
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::error::{ErrorKind, Result};
+use crate::tensor::DType;
+
+/// The NumPy type descriptor for little-endian data of `dtype`. NumPy has no
+/// bfloat16, so those are widened to float32 by [`write_npy`].
+pub fn numpy_descr(dtype: DType) -> &'static str {
+    match dtype {
+        DType::Bool => "|b1",
+        DType::U8 => "|u1",
+        DType::I8 => "|i1",
+        DType::I16 => "<i2",
+        DType::I32 => "<i4",
+        DType::I64 => "<i8",
+        DType::F16 => "<f2",
+        DType::BF16 | DType::F32 => "<f4",
+        DType::F64 => "<f8",
+        DType::Complex64 => "<c8",
+        DType::Complex128 => "<c16",
+    }
+}
+
+/// Writes an array in the `.npy` v1.0 format.
+///
+/// `data` holds the elements of `dtype` in row-major, little-endian order, as
+/// returned by [`Tensor::contiguous_bytes`](crate::Tensor::contiguous_bytes).
+pub fn write_npy<W: Write>(w: &mut W, dtype: DType, shape: &[u64], data: &[u8]) -> Result<()> {
+    let numel = shape.iter().fold(1u64, |numel, &dim| numel.saturating_mul(dim));
+    if numel.checked_mul(dtype.size() as u64) != Some(data.len() as u64) {
+        return Err(ErrorKind::InvalidArgument(format!(
+            "{} bytes of data for a {} array of shape {:?}",
+            data.len(),
+            dtype,
+            shape
+        ))
+        .into());
+    }
+
+    let shape = match shape {
+        [dim] => format!("({},)", dim),
+        _ => format!("({})", shape.iter().map(u64::to_string).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        numpy_descr(dtype),
+        shape
+    );
+
+    // The magic, version and length take 10 bytes, and the header is padded
+    // with spaces and a newline so the data starts 64 byte aligned.
+    let len = (10 + header.len() + 1).next_multiple_of(64) - 10;
+    header.extend(std::iter::repeat_n(' ', len - header.len() - 1));
+    header.push('\n');
+
+    let len = u16::try_from(len).map_err(|_| ErrorKind::Unsupported("npy header over 64KiB".to_string()))?;
+
+    w.write_all(b"\x93NUMPY\x01\x00")?;
+    w.write_u16::<LittleEndian>(len)?;
+    w.write_all(header.as_bytes())?;
+
+    if dtype == DType::BF16 {
+        // bfloat16 is the top half of a float32.
+        for half in data.chunks_exact(2) {
+            w.write_all(&[0, 0, half[0], half[1]])?;
+        }
+    }
+    else {
+        w.write_all(data)?;
+    }
+
+    Ok(())
+}
@@ -112,9 +112,10 @@
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive, IntoPrimitive)]
+/// The raw pickle opcodes as defined by cpython's `pickle.py`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, TryFromPrimitive, IntoPrimitive)]
 #[repr(u8)]
-pub(crate) enum OpCode {
+pub enum OpCode {
     Mark = b'(',
     Stop = b'.',
     Pop = b'0',
@@ -184,3 +185,150 @@ pub(crate) enum OpCode {
     NextBuffer = b'\x97',
     ReadonlyBuffer = b'\x98',
 }
+
+impl OpCode {
+    /// The name `pickletools` uses, such as `SHORT_BINUNICODE`.
+    pub fn name(self) -> &'static str {
+        match self {
+            OpCode::Mark => "MARK",
+            OpCode::Stop => "STOP",
+            OpCode::Pop => "POP",
+            OpCode::PopMark => "POP_MARK",
+            OpCode::Dup => "DUP",
+            OpCode::Float => "FLOAT",
+            OpCode::Int => "INT",
+            OpCode::Binint => "BININT",
+            OpCode::Binint1 => "BININT1",
+            OpCode::Long => "LONG",
+            OpCode::Binint2 => "BININT2",
+            OpCode::None => "NONE",
+            OpCode::Persid => "PERSID",
+            OpCode::Binpersid => "BINPERSID",
+            OpCode::Reduce => "REDUCE",
+            OpCode::String => "STRING",
+            OpCode::Binstring => "BINSTRING",
+            OpCode::ShortBinstring => "SHORT_BINSTRING",
+            OpCode::Unicode => "UNICODE",
+            OpCode::Binunicode => "BINUNICODE",
+            OpCode::Append => "APPEND",
+            OpCode::Build => "BUILD",
+            OpCode::Global => "GLOBAL",
+            OpCode::Dict => "DICT",
+            OpCode::EmptyDict => "EMPTY_DICT",
+            OpCode::Appends => "APPENDS",
+            OpCode::Get => "GET",
+            OpCode::Binget => "BINGET",
+            OpCode::Inst => "INST",
+            OpCode::LongBinget => "LONG_BINGET",
+            OpCode::List => "LIST",
+            OpCode::EmptyList => "EMPTY_LIST",
+            OpCode::Obj => "OBJ",
+            OpCode::Put => "PUT",
+            OpCode::Binput => "BINPUT",
+            OpCode::LongBinput => "LONG_BINPUT",
+            OpCode::Setitem => "SETITEM",
+            OpCode::Tuple => "TUPLE",
+            OpCode::EmptyTuple => "EMPTY_TUPLE",
+            OpCode::Setitems => "SETITEMS",
+            OpCode::Binfloat => "BINFLOAT",
+            OpCode::Proto => "PROTO",
+            OpCode::Newobj => "NEWOBJ",
+            OpCode::Ext1 => "EXT1",
+            OpCode::Ext2 => "EXT2",
+            OpCode::Ext4 => "EXT4",
+            OpCode::Tuple1 => "TUPLE1",
+            OpCode::Tuple2 => "TUPLE2",
+            OpCode::Tuple3 => "TUPLE3",
+            OpCode::Newtrue => "NEWTRUE",
+            OpCode::Newfalse => "NEWFALSE",
+            OpCode::Long1 => "LONG1",
+            OpCode::Long4 => "LONG4",
+            OpCode::Binbytes => "BINBYTES",
+            OpCode::ShortBinbytes => "SHORT_BINBYTES",
+            OpCode::ShortBinunicode => "SHORT_BINUNICODE",
+            OpCode::Binunicode8 => "BINUNICODE8",
+            OpCode::Binbytes8 => "BINBYTES8",
+            OpCode::EmptySet => "EMPTY_SET",
+            OpCode::Additems => "ADDITEMS",
+            OpCode::Frozenset => "FROZENSET",
+            OpCode::NewobjEx => "NEWOBJ_EX",
+            OpCode::StackGlobal => "STACK_GLOBAL",
+            OpCode::Memoize => "MEMOIZE",
+            OpCode::Frame => "FRAME",
+            OpCode::Bytearray8 => "BYTEARRAY8",
+            OpCode::NextBuffer => "NEXT_BUFFER",
+            OpCode::ReadonlyBuffer => "READONLY_BUFFER",
+        }
+    }
+
+    /// The first pickle protocol that has this opcode.
+    pub fn protocol(self) -> u8 {
+        match self {
+            OpCode::Mark
+            | OpCode::Stop
+            | OpCode::Pop
+            | OpCode::Dup
+            | OpCode::Float
+            | OpCode::Int
+            | OpCode::Long
+            | OpCode::None
+            | OpCode::Persid
+            | OpCode::Reduce
+            | OpCode::String
+            | OpCode::Unicode
+            | OpCode::Append
+            | OpCode::Build
+            | OpCode::Global
+            | OpCode::Dict
+            | OpCode::Get
+            | OpCode::Inst
+            | OpCode::List
+            | OpCode::Put
+            | OpCode::Setitem
+            | OpCode::Tuple => 0,
+            OpCode::PopMark
+            | OpCode::Binint
+            | OpCode::Binint1
+            | OpCode::Binint2
+            | OpCode::Binpersid
+            | OpCode::Binstring
+            | OpCode::ShortBinstring
+            | OpCode::Binunicode
+            | OpCode::EmptyDict
+            | OpCode::Appends
+            | OpCode::Binget
+            | OpCode::LongBinget
+            | OpCode::EmptyList
+            | OpCode::Obj
+            | OpCode::Binput
+            | OpCode::LongBinput
+            | OpCode::EmptyTuple
+            | OpCode::Setitems
+            | OpCode::Binfloat => 1,
+            OpCode::Proto
+            | OpCode::Newobj
+            | OpCode::Ext1
+            | OpCode::Ext2
+            | OpCode::Ext4
+            | OpCode::Tuple1
+            | OpCode::Tuple2
+            | OpCode::Tuple3
+            | OpCode::Newtrue
+            | OpCode::Newfalse
+            | OpCode::Long1
+            | OpCode::Long4 => 2,
+            OpCode::Binbytes | OpCode::ShortBinbytes => 3,
+            OpCode::ShortBinunicode
+            | OpCode::Binunicode8
+            | OpCode::Binbytes8
+            | OpCode::EmptySet
+            | OpCode::Additems
+            | OpCode::Frozenset
+            | OpCode::NewobjEx
+            | OpCode::StackGlobal
+            | OpCode::Memoize
+            | OpCode::Frame => 4,
+            OpCode::Bytearray8 | OpCode::NextBuffer | OpCode::ReadonlyBuffer => 5,
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+
+use crate::interpreter::Global;
+
+/// Matches globals by their dotted `module.name` path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GlobalPattern {
+    /// Exactly one global.
+    Exact(Global),
+    /// Every global in a module or any of its submodules.
+    ModulePrefix(String),
+    /// A glob over the dotted path where `*` matches any run of characters.
+    Wildcard(String),
+}
+
+impl GlobalPattern {
+    pub fn matches(&self, global: &Global) -> bool {
+        match self {
+            GlobalPattern::Exact(exact) => exact == global,
+            GlobalPattern::ModulePrefix(prefix) => {
+                let module = global.module();
+                module == prefix
+                    || module.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with('.'))
+            }
+            GlobalPattern::Wildcard(glob) => wildcard_match(glob, &global.to_string()),
+        }
+    }
+}
+
+/// Parses `module.name` as an exact match, `module.*` as a module prefix and
+/// anything else containing `*` as a wildcard.
+impl From<&str> for GlobalPattern {
+    fn from(pattern: &str) -> Self {
+        if let Some(module) = pattern.strip_suffix(".*").filter(|m| !m.contains('*')) {
+            return GlobalPattern::ModulePrefix(module.to_string());
+        }
+
+        if pattern.contains('*') {
+            return GlobalPattern::Wildcard(pattern.to_string());
+        }
+
+        match pattern.rsplit_once('.') {
+            Some((module, name)) => GlobalPattern::Exact(Global::new(module.to_string(), name.to_string())),
+            None => GlobalPattern::Exact(Global::new("builtins", pattern.to_string())),
+        }
+    }
+}
+
+impl From<Global> for GlobalPattern {
+    fn from(global: Global) -> Self {
+        GlobalPattern::Exact(global)
+    }
+}
+
+impl fmt::Display for GlobalPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobalPattern::Exact(global) => write!(f, "{global}"),
+            GlobalPattern::ModulePrefix(module) => write!(f, "{module}.*"),
+            GlobalPattern::Wildcard(glob) => write!(f, "{glob}"),
+        }
+    }
+}
+
+fn wildcard_match(glob: &str, text: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` at all, so the glob must have matched exactly.
+        return rest.is_empty();
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// How a global fared against a [`GlobalPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Classification {
+    Allowed,
+    Denied,
+    /// Matched neither the allow nor the deny list.
+    Unknown,
+}
+
+/// What the interpreter does when it meets a denied global.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolicyMode {
+    /// Fail the load with [`ErrorKind::PolicyViolation`](crate::ErrorKind::PolicyViolation).
+    Abort,
+    /// Keep loading and remember the global, see [`Interpreter::violations`](crate::Interpreter::violations).
+    #[default]
+    Record,
+}
+
+/// Decides which globals a pickle may reference.
+///
+/// Deny patterns win over allow patterns. Globals matching neither are
+/// [`Classification::Unknown`] and are only treated as violations when
+/// [`GlobalPolicy::deny_unknown`] is set.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalPolicy {
+    allow: Vec<GlobalPattern>,
+    deny: Vec<GlobalPattern>,
+    deny_unknown: bool,
+    pub(crate) mode: PolicyMode,
+}
+
+/// `torch.<dtype>` names, which is how dtypes are pickled.
+const TORCH_DTYPES: &[&str] = &[
+    "float64", "float32", "float16", "bfloat16", "complex128", "complex64", "complex32",
+    "int64", "int32", "int16", "int8", "uint64", "uint32", "uint16", "uint8", "bool",
+    "qint8", "quint8", "qint32", "quint4x2", "quint2x4",
+    "float8_e5m2", "float8_e4m3fn", "float8_e5m2fnuz", "float8_e4m3fnuz",
+    "bits8", "bits16", "bits1x8", "bits2x4", "bits4x2",
+];
+
+const TORCH_QSCHEMES: &[&str] = &[
+    "per_tensor_affine", "per_tensor_symmetric", "per_channel_affine", "per_channel_symmetric",
+    "per_channel_affine_float_qparams",
+];
+
+const TORCH_TENSOR_TYPES: &[&str] = &[
+    "DoubleTensor", "FloatTensor", "HalfTensor", "BFloat16Tensor", "LongTensor", "IntTensor",
+    "ShortTensor", "CharTensor", "ByteTensor", "BoolTensor",
+];
+
+const TORCH_STORAGE_TYPES: &[&str] = &[
+    "DoubleStorage", "FloatStorage", "HalfStorage", "BFloat16Storage", "ComplexDoubleStorage",
+    "ComplexFloatStorage", "LongStorage", "IntStorage", "ShortStorage", "CharStorage", "ByteStorage",
+    "BoolStorage", "QUInt8Storage", "QInt8Storage", "QInt32Storage", "QUInt4x2Storage", "QUInt2x4Storage",
+];
+
+const TORCH_CUDA_STORAGE_TYPES: &[&str] = &[
+    "DoubleStorage", "FloatStorage", "HalfStorage", "BFloat16Storage", "ComplexDoubleStorage",
+    "ComplexFloatStorage", "LongStorage", "IntStorage", "ShortStorage", "CharStorage", "ByteStorage",
+    "BoolStorage",
+];
+
+const TORCH_REBUILD_FUNCTIONS: &[&str] = &[
+    "_rebuild_parameter", "_rebuild_parameter_with_state", "_rebuild_qtensor", "_rebuild_tensor",
+    "_rebuild_tensor_v2", "_rebuild_tensor_v3", "_rebuild_sparse_tensor", "_rebuild_meta_tensor_no_storage",
+    "_rebuild_nested_tensor", "_rebuild_wrapper_subclass", "_rebuild_device_tensor_from_numpy",
+    "_rebuild_device_tensor_from_cpu_tensor",
+];
+
+impl GlobalPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only the rebuild functions and types a plain PyTorch `state_dict`
+    /// needs, aborting on anything else.
+    pub fn torch_state_dict() -> Self {
+        let mut policy = GlobalPolicy::new()
+            .allow("collections.OrderedDict")
+            .allow("torch._utils._rebuild_tensor")
+            .allow("torch._utils._rebuild_tensor_v2")
+            .allow("torch._utils._rebuild_parameter")
+            .allow("torch._utils._rebuild_parameter_with_state")
+            .allow("torch.Size")
+            .allow("torch.device")
+            .allow("torch.*Storage")
+            .allow("torch.storage.UntypedStorage")
+            .allow("torch.storage.TypedStorage")
+            .allow("numpy.core.multiarray._reconstruct")
+            .allow("numpy.ndarray")
+            .allow("numpy.dtype")
+            .deny_unknown(true)
+            .mode(PolicyMode::Abort);
+
+        for dtype in [
+            "float64", "float32", "float16", "bfloat16", "complex64", "complex128",
+            "int64", "int32", "int16", "int8", "uint8", "bool",
+        ] {
+            policy = policy.allow(GlobalPattern::Exact(Global::new("torch", dtype)));
+        }
+
+        policy
+    }
+
+    /// Exactly the globals `torch.load(weights_only=True)` allows by default,
+    /// aborting on anything else.
+    pub fn torch_weights_only() -> Self {
+        let mut policy = GlobalPolicy::new().deny_unknown(true).mode(PolicyMode::Abort);
+
+        fn exact(module: &'static str, names: &'static [&'static str]) -> impl Iterator<Item = GlobalPattern> {
+            names.iter().map(move |&name| GlobalPattern::Exact(Global::new(module, name)))
+        }
+        let patterns = exact("collections", &["OrderedDict", "Counter"])
+            .chain(exact("torch.nn.parameter", &["Parameter"]))
+            .chain(exact("torch.serialization", &["_get_layout"]))
+            .chain(exact("torch", &["Size", "Tensor", "device"]))
+            .chain(exact("_codecs", &["encode"]))
+            // torch maps python 2 names in protocol 2 pickles, as python does.
+            .chain(exact("builtins", &["bytearray", "set", "complex"]))
+            .chain(exact("__builtin__", &["bytearray", "set", "complex"]))
+            .chain(exact("torch", TORCH_DTYPES))
+            .chain(exact("torch", TORCH_QSCHEMES))
+            .chain(exact("torch", TORCH_TENSOR_TYPES))
+            .chain(exact("torch.cuda", TORCH_TENSOR_TYPES))
+            .chain(exact("torch", TORCH_STORAGE_TYPES))
+            .chain(exact("torch.cuda", TORCH_CUDA_STORAGE_TYPES))
+            .chain(exact("torch.storage", &["UntypedStorage", "TypedStorage"]))
+            .chain(exact("torch._utils", TORCH_REBUILD_FUNCTIONS))
+            .chain(exact("torch._tensor", &["_rebuild_from_type_v2"]));
+
+        for pattern in patterns {
+            policy = policy.allow(pattern);
+        }
+
+        policy
+    }
+
+    pub fn allow(mut self, pattern: impl Into<GlobalPattern>) -> Self {
+        self.allow.push(pattern.into());
+        self
+    }
+
+    pub fn deny(mut self, pattern: impl Into<GlobalPattern>) -> Self {
+        self.deny.push(pattern.into());
+        self
+    }
+
+    /// Treat globals matching no pattern as if they were denied.
+    pub fn deny_unknown(mut self, deny_unknown: bool) -> Self {
+        self.deny_unknown = deny_unknown;
+        self
+    }
+
+    pub fn mode(mut self, mode: PolicyMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn classify(&self, global: &Global) -> Classification {
+        if self.deny.iter().any(|p| p.matches(global)) {
+            Classification::Denied
+        }
+        else if self.allow.iter().any(|p| p.matches(global)) {
+            Classification::Allowed
+        }
+        else {
+            Classification::Unknown
+        }
+    }
+
+    /// Whether referencing `global` breaks this policy.
+    pub fn is_violation(&self, global: &Global) -> bool {
+        match self.classify(global) {
+            Classification::Allowed => false,
+            Classification::Denied => true,
+            Classification::Unknown => self.deny_unknown,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde_json::{json, Map};
+
+use crate::error::{ErrorKind, Result};
+use crate::tensor::DType;
+
+/// One tensor of a safetensors file, listed in the order its data is written.
+#[derive(Debug, Clone)]
+pub struct SafetensorsTensor<'a> {
+    pub name: &'a str,
+    pub dtype: DType,
+    pub shape: &'a [u64],
+}
+
+impl SafetensorsTensor<'_> {
+    /// Bytes of data this tensor occupies after the header.
+    pub fn byte_len(&self) -> u64 {
+        self.shape
+            .iter()
+            .fold(self.dtype.size() as u64, |len, &dim| len.saturating_mul(dim))
+    }
+}
+
+fn dtype_name(dtype: DType) -> Result<&'static str> {
+    let name = match dtype {
+        DType::Bool => "BOOL",
+        DType::U8 => "U8",
+        DType::I8 => "I8",
+        DType::I16 => "I16",
+        DType::I32 => "I32",
+        DType::I64 => "I64",
+        DType::F16 => "F16",
+        DType::BF16 => "BF16",
+        DType::F32 => "F32",
+        DType::F64 => "F64",
+        DType::Complex64 | DType::Complex128 => {
+            return Err(ErrorKind::Unsupported(format!("{} tensors in safetensors", dtype)).into());
+        }
+    };
+
+    Ok(name)
+}
+
+/// Writes the length-prefixed JSON header of a safetensors file.
+///
+/// The little-endian, row-major data of each tensor must follow in the same
+/// order, [`SafetensorsTensor::byte_len`] bytes each.
+pub fn write_safetensors_header<W: Write>(
+    w: &mut W,
+    tensors: &[SafetensorsTensor],
+    metadata: &BTreeMap<String, String>,
+) -> Result<()> {
+    let mut header = Map::new();
+    let mut names = HashSet::new();
+    let mut offset: u64 = 0;
+
+    if !metadata.is_empty() {
+        header.insert("__metadata__".to_string(), json!(metadata));
+    }
+
+    for tensor in tensors.iter() {
+        if tensor.name == "__metadata__" || !names.insert(tensor.name) {
+            return Err(ErrorKind::InvalidArgument(format!("duplicate tensor name {:?}", tensor.name)).into());
+        }
+
+        let end = offset.saturating_add(tensor.byte_len());
+        header.insert(
+            tensor.name.to_string(),
+            json!({
+                "dtype": dtype_name(tensor.dtype)?,
+                "shape": tensor.shape,
+                "data_offsets": [offset, end],
+            }),
+        );
+        offset = end;
+    }
+
+    let mut header = serde_json::to_vec(&header).map_err(|err| ErrorKind::InvalidArgument(err.to_string()))?;
+    // Pad with spaces so the data that follows is 8 byte aligned.
+    header.resize(header.len().next_multiple_of(8), b' ');
+
+    w.write_u64::<LittleEndian>(header.len() as u64)?;
+    w.write_all(&header)?;
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::error::{ErrorKind, Result};
+use crate::interpreter::{Global, Value};
+use crate::policy::{GlobalPolicy, PolicyMode};
+use crate::tensor::Storage;
+
+/// Removes every call to, or reference of, a global `policy` does not allow
+/// from a depickled `value`, returning the globals removed.
+///
+/// Offending [`Value::Reduce`]s and [`Value::Global`]s are replaced with
+/// `None`, as are objects whose BUILD state was set on one. With
+/// [`PolicyMode::Abort`] the first offending global fails with
+/// [`ErrorKind::PolicyViolation`] instead. Persistent ids other than torch
+/// storages can not be written back in a form torch accepts, so they always
+/// fail.
+///
+/// Native values such as [`Value::Tensor`] and [`Value::FrozenSet`] are
+/// checked against the globals [`PickleWriter`](crate::PickleWriter) writes
+/// them with, so with [`GlobalPolicy::torch_weights_only`] the result only
+/// references globals torch's `weights_only` loader allows.
+pub fn sanitize(value: &mut Value, policy: &GlobalPolicy) -> Result<Vec<Global>> {
+    let mut removed = Vec::new();
+    strip(value, policy, &mut removed)?;
+    Ok(removed)
+}
+
+fn strip(value: &mut Value, policy: &GlobalPolicy, removed: &mut Vec<Global>) -> Result<()> {
+    let mut offending = |global: &Global| -> Result<bool> {
+        if !policy.is_violation(global) {
+            return Ok(false);
+        }
+        if policy.mode == PolicyMode::Abort {
+            return Err(ErrorKind::PolicyViolation(global.clone()).into());
+        }
+
+        removed.push(global.clone());
+        Ok(true)
+    };
+
+    for global in written_globals(value) {
+        if offending(&global)? {
+            *value = Value::None;
+            return Ok(());
+        }
+    }
+
+    match value {
+        Value::Global(_) | Value::Function(_) => {
+            if let Some(global) = as_global(value) {
+                if offending(&global)? {
+                    *value = Value::None;
+                }
+            }
+        }
+        Value::Reduce(func, args) => {
+            match as_global(func) {
+                Some(global) if offending(&global)? => *value = Value::None,
+                Some(_) => strip(args, policy, removed)?,
+                None => {
+                    strip(func, policy, removed)?;
+                    strip(args, policy, removed)?;
+                }
+            }
+        }
+        Value::SetState(inst, state) => {
+            strip(inst, policy, removed)?;
+            if inst.is_none() {
+                *value = Value::None;
+            }
+            else {
+                strip(state, policy, removed)?;
+            }
+        }
+        Value::PersistentLoad(pid) => {
+            return Err(ErrorKind::Unsupported(format!("persistent id {:?}", pid)).into());
+        }
+        Value::Dict(dict) => {
+            for (k, v) in dict.iter_mut() {
+                strip(k, policy, removed)?;
+                strip(v, policy, removed)?;
+            }
+        }
+        Value::OrderedDict(dict) => {
+            for (k, v) in dict.iter_mut() {
+                strip(k, policy, removed)?;
+                strip(v, policy, removed)?;
+            }
+        }
+        Value::Tuple(items) | Value::List(items) => {
+            for item in items.iter_mut() {
+                strip(item, policy, removed)?;
+            }
+        }
+        Value::Set(set) | Value::FrozenSet(set) => {
+            for item in set.iter_mut() {
+                strip(item, policy, removed)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// The globals the writer references to rebuild a native value.
+fn written_globals(value: &Value) -> Vec<Global> {
+    let storage = |storage: &Storage| Global::new("torch", storage.dtype.storage_name());
+
+    match value {
+        Value::Bytes(_) => vec![Global::new("_codecs", "encode")],
+        Value::ByteArray(_) => vec![Global::new("builtins", "bytearray")],
+        Value::Set(_) => vec![Global::new("builtins", "set")],
+        Value::FrozenSet(_) => vec![Global::new("builtins", "frozenset")],
+        Value::OrderedDict(_) => vec![Global::new("collections", "OrderedDict")],
+        Value::Storage(s) => vec![storage(s)],
+        Value::Tensor(tensor) => {
+            let mut globals = vec![
+                Global::new("torch._utils", "_rebuild_tensor_v2"),
+                storage(&tensor.storage),
+                Global::new("collections", "OrderedDict"),
+            ];
+            if tensor.parameter {
+                globals.push(Global::new("torch._utils", "_rebuild_parameter"));
+            }
+            globals
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The global a value refers to, including built-in functions it resolved to.
+fn as_global(value: &Value) -> Option<Global> {
+    match value {
+        Value::Global(global) => Some(global.clone()),
+        Value::Function(function) => function.global(),
+        _ => None,
+    }
+}
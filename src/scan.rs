@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use serde_json::{json, Value as Json};
+
+use crate::ast::Op;
+use crate::decoder::PickleReader;
+use crate::error::{Error, ErrorKind};
+use crate::interpreter::{Global, Interpreter, Value};
+use crate::opcodes::OpCode;
+use crate::policy::{Classification, GlobalPolicy};
+
+pub(crate) mod sarif;
+
+/// Callables that let a pickle run code, touch the filesystem or network, or
+/// load further pickles.
+const DANGEROUS_GLOBALS: &[&str] = &[
+    "os.*",
+    "posix.*",
+    "nt.*",
+    "subprocess.*",
+    "pty.*",
+    "runpy.*",
+    "webbrowser.*",
+    "socket.*",
+    "shutil.*",
+    "ctypes.*",
+    "importlib.*",
+    "marshal.*",
+    "code.*",
+    "commands.*",
+    "pickle.*",
+    "_pickle.*",
+    "cPickle.*",
+    "dill.*",
+    "joblib.load",
+    "torch.load",
+    "numpy.load",
+    "platform.popen",
+    "sys.*",
+    "builtins.eval",
+    "builtins.exec",
+    "builtins.compile",
+    "builtins.open",
+    "builtins.__import__",
+    "builtins.getattr",
+    "builtins.setattr",
+    "builtins.delattr",
+    "builtins.globals",
+    "builtins.locals",
+    "builtins.breakpoint",
+    "builtins.input",
+    "builtins.apply",
+    "builtins.execfile",
+    "__builtin__.eval",
+    "__builtin__.exec",
+    "__builtin__.compile",
+    "__builtin__.open",
+    "__builtin__.file",
+    "__builtin__.__import__",
+    "__builtin__.getattr",
+    "__builtin__.setattr",
+    "__builtin__.delattr",
+    "__builtin__.globals",
+    "__builtin__.locals",
+    "__builtin__.input",
+    "__builtin__.apply",
+    "__builtin__.execfile",
+];
+
+/// Callables plain data and model checkpoints are expected to use.
+const SAFE_GLOBALS: &[&str] = &[
+    "collections.OrderedDict",
+    "collections.defaultdict",
+    "collections.Counter",
+    "collections.deque",
+    "builtins.set",
+    "builtins.frozenset",
+    "builtins.bytes",
+    "builtins.bytearray",
+    "builtins.complex",
+    "builtins.slice",
+    "builtins.range",
+    "__builtin__.set",
+    "__builtin__.frozenset",
+    "__builtin__.bytes",
+    "__builtin__.bytearray",
+    "__builtin__.complex",
+    "__builtin__.slice",
+    "_codecs.encode",
+    "copy_reg._reconstructor",
+    "copyreg._reconstructor",
+    "torch._utils.*",
+    "torch.*Storage",
+    "torch.storage.*",
+    "torch.Size",
+    "torch.device",
+    "torch.float64",
+    "torch.float32",
+    "torch.float16",
+    "torch.bfloat16",
+    "torch.int64",
+    "torch.int32",
+    "torch.int16",
+    "torch.int8",
+    "torch.uint8",
+    "torch.bool",
+    "numpy.core.multiarray._reconstruct",
+    "numpy.core.multiarray.scalar",
+    "numpy._core.multiarray._reconstruct",
+    "numpy._core.multiarray.scalar",
+    "numpy.ndarray",
+    "numpy.dtype",
+];
+
+/// How worrying a finding, or a whole pickle, is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Only references callables on the built-in safe list.
+    Safe,
+    /// References callables on neither list.
+    Suspicious,
+    /// References a known-dangerous callable.
+    Malicious,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Safe => "safe",
+            Severity::Suspicious => "suspicious",
+            Severity::Malicious => "malicious",
+        })
+    }
+}
+
+/// What a pickle does with a global.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    /// GLOBAL, STACK_GLOBAL or INST naming a callable.
+    Reference,
+    /// REDUCE, NEWOBJ, NEWOBJ_EX, OBJ or INST calling it.
+    Call,
+    /// BUILD setting state on the result of calling it.
+    Build,
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FindingKind::Reference => "reference",
+            FindingKind::Call => "call",
+            FindingKind::Build => "build",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    /// Byte offset of the op from the start of the stream.
+    pub offset: u64,
+    pub opcode: OpCode,
+    pub kind: FindingKind,
+    pub global: Global,
+    pub severity: Severity,
+}
+
+/// Everything found in one pickle.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub findings: Vec<Finding>,
+    /// The first decode or interpreter error. Ops after a decode error were
+    /// not looked at.
+    pub error: Option<Error>,
+}
+
+impl ScanReport {
+    /// The worst severity of any finding. A pickle that could not be read to
+    /// the end is at least suspicious.
+    pub fn severity(&self) -> Severity {
+        let worst = self
+            .findings
+            .iter()
+            .map(|finding| finding.severity)
+            .max()
+            .unwrap_or(Severity::Safe);
+
+        match self.error {
+            Some(_) => worst.max(Severity::Suspicious),
+            None => worst,
+        }
+    }
+}
+
+/// The scan of one pickle in a model file.
+#[derive(Debug)]
+pub struct MemberReport {
+    pub path: PathBuf,
+    /// The pickle's name within the model file, `None` for a bare pickle.
+    pub member: Option<String>,
+    pub report: ScanReport,
+}
+
+impl MemberReport {
+    /// Each finding, then the error if there was one, as a JSON object.
+    pub fn json_findings(&self) -> Vec<Json> {
+        let findings = self.report.findings.iter().map(|finding| self.finding_json(finding));
+        findings.chain(self.report.error.iter().map(|err| self.error_json(err))).collect()
+    }
+
+    fn finding_json(&self, finding: &Finding) -> Json {
+        json!({
+            "path": self.path.display().to_string(),
+            "member": self.member,
+            "offset": finding.offset,
+            "opcode": format!("{:?}", finding.opcode),
+            "kind": finding.kind.to_string(),
+            "global": finding.global.to_string(),
+            "severity": finding.severity.to_string(),
+        })
+    }
+
+    fn error_json(&self, err: &Error) -> Json {
+        json!({
+            "path": self.path.display().to_string(),
+            "member": self.member,
+            "offset": err.offset(),
+            "opcode": err.opcode().map(|opcode| format!("{:?}", opcode)),
+            "kind": "error",
+            "error": err.kind().to_string(),
+            "severity": Severity::Suspicious.to_string(),
+        })
+    }
+}
+
+/// Flags pickles that reference dangerous callables without running them.
+///
+/// The scanner walks the ops from [`PickleReader`] for global references and
+/// runs them through an [`Interpreter`] so the callable behind each REDUCE,
+/// NEWOBJ and BUILD can be read off the stack. When the interpreter gives up
+/// on a malformed pickle the remaining ops are still checked for references.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    policy: GlobalPolicy,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scanner {
+    /// A scanner using the built-in lists of dangerous and safe callables.
+    pub fn new() -> Self {
+        let policy = DANGEROUS_GLOBALS
+            .iter()
+            .fold(GlobalPolicy::new(), |policy, &pattern| policy.deny(pattern));
+        let policy = SAFE_GLOBALS
+            .iter()
+            .fold(policy, |policy, &pattern| policy.allow(pattern));
+
+        Self::with_policy(policy)
+    }
+
+    /// A scanner where denied globals are malicious, unknown ones suspicious
+    /// and allowed ones safe.
+    pub fn with_policy(policy: GlobalPolicy) -> Self {
+        Scanner { policy }
+    }
+
+    pub fn severity(&self, global: &Global) -> Severity {
+        match self.policy.classify(global) {
+            Classification::Allowed => Severity::Safe,
+            Classification::Unknown => Severity::Suspicious,
+            Classification::Denied => Severity::Malicious,
+        }
+    }
+
+    /// Scans the first pickle in `pickle_file`.
+    pub fn scan<R: BufRead>(&self, pickle_file: R) -> ScanReport {
+        self.scan_pickle(&mut PickleReader::new(pickle_file), false)
+    }
+
+    /// Scans every pickle written back to back in `pickle_file`, one report
+    /// each, with offsets from the start of the stream. Input after the last
+    /// pickle whose first op does not decode is reported as
+    /// [`ErrorKind::TrailingData`], and scanning stops at the first report
+    /// with an error.
+    pub fn scan_all<R: BufRead>(&self, pickle_file: R) -> Vec<ScanReport> {
+        let mut reader = PickleReader::new(pickle_file);
+        let mut reports = Vec::new();
+
+        loop {
+            let report = self.scan_pickle(&mut reader, !reports.is_empty());
+            let failed = report.error.is_some();
+            reports.push(report);
+
+            if failed {
+                break;
+            }
+
+            reader.next_pickle();
+            match reader.at_eof() {
+                Ok(false) => {}
+                Ok(true) => break,
+                Err(err) => {
+                    reports.push(ScanReport { findings: Vec::new(), error: Some(err) });
+                    break;
+                }
+            }
+        }
+
+        reports
+    }
+
+    fn scan_pickle<R: BufRead>(&self, reader: &mut PickleReader<R>, later: bool) -> ScanReport {
+        let start = PickleReader::position(reader);
+        let mut interp = Some(Interpreter::new());
+        // Globals the interpreter resolved to built-in functions, by name.
+        let mut functions = HashMap::new();
+        let mut stopped = false;
+        let mut report = ScanReport::default();
+        // Only input that does not start like a pickle is trailing data.
+        let mut trailing = later;
+
+        loop {
+            let op = match reader.next() {
+                None => break,
+                Some(Err(err)) => {
+                    report.error.get_or_insert(if trailing { err.into_trailing_data(start) } else { err });
+                    break;
+                }
+                Some(Ok(op)) => op,
+            };
+            trailing = false;
+            let Some(opcode) = reader.op_code() else {
+                continue;
+            };
+            let offset = reader.op_offset();
+            let stack = interp.as_ref().map(Interpreter::stack).unwrap_or_default();
+
+            let callee = |value: &Value| match value {
+                Value::Global(global) => Some(global.clone()),
+                Value::Function(function) => functions.get(function.name()).cloned(),
+                _ => None,
+            };
+
+            let mut record = |kind, global: Global| {
+                report.findings.push(Finding {
+                    offset,
+                    opcode,
+                    kind,
+                    severity: self.severity(&global),
+                    global,
+                });
+            };
+
+            let reference = match &op {
+                Op::Global(module, name) | Op::Inst(module, name) => {
+                    Some(Global::new(module.clone(), name.clone()))
+                }
+                Op::StackGlobal => match stack {
+                    [.., Value::String(module), Value::String(name)] => {
+                        Some(Global::new(module.clone(), name.clone()))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            if let Some(global) = &reference {
+                record(FindingKind::Reference, global.clone());
+            }
+
+            let called = match &op {
+                Op::Inst(..) => reference.clone(),
+                Op::Reduce | Op::NewObj => stack.iter().nth_back(1).and_then(callee),
+                Op::NewObjEx => stack.iter().nth_back(2).and_then(callee),
+                Op::Obj => stack.first().and_then(callee),
+                _ => None,
+            };
+
+            if let Some(global) = called {
+                record(FindingKind::Call, global);
+            }
+
+            if let (Op::Build, Some(Value::Reduce(func, _))) = (&op, stack.iter().nth_back(1)) {
+                if let Some(global) = callee(func) {
+                    record(FindingKind::Build, global);
+                }
+            }
+
+            if let Some(running) = interp.as_mut() {
+                match running.exec_op(op) {
+                    Ok(_) => {
+                        if let (Some(global), Some(Value::Function(function))) =
+                            (reference, running.stack().last())
+                        {
+                            functions.insert(function.name().to_string(), global);
+                        }
+                    }
+                    Err(err) => {
+                        report.error.get_or_insert(err.at(offset, Some(opcode)));
+                        interp = None;
+                    }
+                }
+            }
+
+            if opcode == OpCode::Stop {
+                stopped = true;
+                break;
+            }
+        }
+
+        if !stopped && report.error.is_none() {
+            report.error = Some(Error::from(ErrorKind::Truncated).at(PickleReader::position(reader), None));
+        }
+
+        report
+    }
+}
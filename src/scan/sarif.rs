@@ -0,0 +1,122 @@
+use serde_json::{json, Value as Json};
+
+use crate::scan::{MemberReport, Severity};
+
+/// Builds a SARIF 2.1.0 log of everything that is not safe. Each pickle is an
+/// artifact nested inside its model file. Offsets count from the start of the
+/// stream the pickle was read from, so for the `pickle-N` members of a file
+/// holding several pickles they are offsets into the file, not the pickle.
+/// Errors without an offset have no region.
+pub fn sarif_log(reports: &[MemberReport]) -> Json {
+    let mut artifacts: Vec<Json> = Vec::new();
+    let mut results = Vec::new();
+    let mut parents: Vec<(String, usize)> = Vec::new();
+
+    for report in reports.iter() {
+        let path = report.path.display().to_string();
+
+        let parent = match parents.iter().find(|(p, _)| *p == path) {
+            Some((_, index)) => *index,
+            None => {
+                artifacts.push(json!({ "location": { "uri": path } }));
+                parents.push((path.clone(), artifacts.len() - 1));
+                artifacts.len() - 1
+            }
+        };
+
+        // A bare pickle is the file itself rather than a member nested in it.
+        let (uri, index) = match &report.member {
+            Some(member) => {
+                artifacts.push(json!({
+                    "location": { "uri": member },
+                    "parentIndex": parent,
+                }));
+                (member.clone(), artifacts.len() - 1)
+            }
+            None => (path.clone(), parent),
+        };
+
+        let mut result = |rule: &str, level: &str, message: String, offset: Option<u64>, subject: String, properties: Json| {
+            let mut physical_location = json!({
+                "artifactLocation": { "uri": uri, "index": index },
+            });
+            // SARIF requires a region to say where it is, so leave it out
+            // rather than write an empty one.
+            if let Some(offset) = offset {
+                physical_location["region"] = json!({ "byteOffset": offset });
+            }
+
+            results.push(json!({
+                "ruleId": rule,
+                "level": level,
+                "message": { "text": message },
+                "locations": [{ "physicalLocation": physical_location }],
+                // Offsets shift between model versions, so leave them out.
+                "partialFingerprints": {
+                    "pickleGlobal/v1": format!("{}:{}", report.member.as_deref().unwrap_or_default(), subject),
+                },
+                "properties": properties,
+            }));
+        };
+
+        for finding in report.report.findings.iter() {
+            let (rule, level) = match finding.severity {
+                Severity::Safe => continue,
+                Severity::Suspicious => ("unknown-global", "warning"),
+                Severity::Malicious => ("dangerous-global", "error"),
+            };
+
+            result(
+                rule,
+                level,
+                format!("{} {} of {}", finding.severity, finding.kind, finding.global),
+                Some(finding.offset),
+                format!("{}:{}", finding.kind, finding.global),
+                report.finding_json(finding),
+            );
+        }
+
+        if let Some(err) = &report.report.error {
+            result(
+                "unreadable-pickle",
+                "warning",
+                format!("{} could not be read: {}", uri, err.kind()),
+                err.offset(),
+                format!("error:{}", err.kind()),
+                report.error_json(err),
+            );
+        }
+    }
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": [
+                        {
+                            "id": "dangerous-global",
+                            "shortDescription": { "text": "Pickle references a known-dangerous callable" },
+                            "defaultConfiguration": { "level": "error" },
+                        },
+                        {
+                            "id": "unknown-global",
+                            "shortDescription": { "text": "Pickle references a callable on neither the safe nor the dangerous list" },
+                            "defaultConfiguration": { "level": "warning" },
+                        },
+                        {
+                            "id": "unreadable-pickle",
+                            "shortDescription": { "text": "Pickle could not be read to the end" },
+                            "defaultConfiguration": { "level": "warning" },
+                        },
+                    ],
+                },
+            },
+            "artifacts": artifacts,
+            "results": results,
+        }],
+    })
+}
@@ -0,0 +1,373 @@
+use std::fmt;
+
+use crate::error::{Error, ErrorKind, Limit, Result};
+use crate::interpreter::{Global, Value};
+
+/// Element type of a PyTorch storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DType {
+    Bool,
+    U8,
+    I8,
+    I16,
+    I32,
+    I64,
+    F16,
+    BF16,
+    F32,
+    F64,
+    Complex64,
+    Complex128,
+}
+
+impl DType {
+    /// Bytes per element.
+    pub fn size(self) -> usize {
+        match self {
+            DType::Bool | DType::U8 | DType::I8 => 1,
+            DType::I16 | DType::F16 | DType::BF16 => 2,
+            DType::I32 | DType::F32 => 4,
+            DType::I64 | DType::F64 | DType::Complex64 => 8,
+            DType::Complex128 => 16,
+        }
+    }
+
+    /// Parses a storage class name such as `FloatStorage`. `UntypedStorage`
+    /// holds raw bytes.
+    pub fn from_storage_name(name: &str) -> Option<Self> {
+        let dtype = match name {
+            "BoolStorage" => DType::Bool,
+            "ByteStorage" | "UntypedStorage" => DType::U8,
+            "CharStorage" => DType::I8,
+            "ShortStorage" => DType::I16,
+            "IntStorage" => DType::I32,
+            "LongStorage" => DType::I64,
+            "HalfStorage" => DType::F16,
+            "BFloat16Storage" => DType::BF16,
+            "FloatStorage" => DType::F32,
+            "DoubleStorage" => DType::F64,
+            "ComplexFloatStorage" => DType::Complex64,
+            "ComplexDoubleStorage" => DType::Complex128,
+            _ => return None,
+        };
+
+        Some(dtype)
+    }
+
+    /// The storage class torch pickles data of this type as.
+    pub fn storage_name(self) -> &'static str {
+        match self {
+            DType::Bool => "BoolStorage",
+            DType::U8 => "ByteStorage",
+            DType::I8 => "CharStorage",
+            DType::I16 => "ShortStorage",
+            DType::I32 => "IntStorage",
+            DType::I64 => "LongStorage",
+            DType::F16 => "HalfStorage",
+            DType::BF16 => "BFloat16Storage",
+            DType::F32 => "FloatStorage",
+            DType::F64 => "DoubleStorage",
+            DType::Complex64 => "ComplexFloatStorage",
+            DType::Complex128 => "ComplexDoubleStorage",
+        }
+    }
+}
+
+/// Prints the torch name, such as `float32`.
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DType::Bool => "bool",
+            DType::U8 => "uint8",
+            DType::I8 => "int8",
+            DType::I16 => "int16",
+            DType::I32 => "int32",
+            DType::I64 => "int64",
+            DType::F16 => "float16",
+            DType::BF16 => "bfloat16",
+            DType::F32 => "float32",
+            DType::F64 => "float64",
+            DType::Complex64 => "complex64",
+            DType::Complex128 => "complex128",
+        })
+    }
+}
+
+/// A PyTorch storage saved alongside the pickle, resolved from its
+/// `('storage', storage_type, key, location, numel)` persistent id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Storage {
+    pub dtype: DType,
+    /// Name of the zip entry under `<archive>/data/` holding the raw bytes.
+    pub key: String,
+    /// Device the storage was saved from, such as `cpu` or `cuda:0`.
+    pub location: String,
+    /// Number of `dtype` elements in the storage.
+    pub numel: u64,
+    /// Whether the persistent id ended with the `view_metadata` entry legacy
+    /// checkpoints add, so it can be written back the way torch reads it.
+    /// Views of other storages are not supported, so the entry is `None`.
+    pub has_view_metadata: bool,
+}
+
+impl Storage {
+    /// Parses a persistent id, returning `None` for anything that is not a
+    /// torch storage. Legacy checkpoints add a sixth `view_metadata` entry,
+    /// which is `None` unless the storage is a view of another.
+    pub(crate) fn from_pid(pid: &Value) -> Option<Self> {
+        let [tag, storage_type, key, location, numel, rest @ ..] = pid.as_tuple()? else {
+            return None;
+        };
+
+        if tag.as_str()? != "storage" || !matches!(rest, [] | [Value::None]) {
+            return None;
+        }
+
+        Some(Storage {
+            dtype: DType::from_storage_name(storage_type.as_global()?.name())?,
+            key: key.as_str()?.to_string(),
+            location: location.as_str()?.to_string(),
+            numel: numel.as_u64()?,
+            has_view_metadata: !rest.is_empty(),
+        })
+    }
+
+    /// The zip entry holding this storage, for a checkpoint whose pickle is
+    /// `<archive>/data.pkl`.
+    pub fn data_entry(&self, archive: &str) -> String {
+        format!("{}/data/{}", archive, self.key)
+    }
+
+    /// Size of the storage's data in bytes.
+    pub fn byte_len(&self) -> u64 {
+        self.numel.saturating_mul(self.dtype.size() as u64)
+    }
+}
+
+/// A tensor rebuilt by `torch._utils._rebuild_tensor_v2` and friends. The
+/// data itself stays in the archive, see [`Storage::data_entry`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    pub storage: Storage,
+    /// Offset of the first element into the storage, in elements.
+    pub storage_offset: u64,
+    pub shape: Vec<u64>,
+    /// Distance between consecutive indices of each dimension, in elements.
+    pub strides: Vec<u64>,
+    pub requires_grad: bool,
+    /// Whether the tensor was saved as an `nn.Parameter`, wrapped in
+    /// `_rebuild_parameter`.
+    pub parameter: bool,
+}
+
+/// How many times larger than its storage a strided tensor may get when made
+/// contiguous, past [`MIN_TENSOR_CAP`].
+const MAX_EXPANSION: u64 = 64;
+
+/// Bytes any strided tensor may take when made contiguous, so small
+/// broadcast tensors are always fine.
+const MIN_TENSOR_CAP: u64 = 1 << 20;
+
+impl Tensor {
+    pub fn dtype(&self) -> DType {
+        self.storage.dtype
+    }
+
+    /// Number of elements, saturating for shapes too large to exist.
+    pub fn numel(&self) -> u64 {
+        self.shape.iter().fold(1, |numel, &dim| numel.saturating_mul(dim))
+    }
+
+    /// Copies the tensor's elements out of its storage's raw bytes into
+    /// row-major order, converting them to little-endian.
+    ///
+    /// Strided tensors over 64 times the size of their storage, and over
+    /// 1 MiB, fail with [`ErrorKind::LimitExceeded`].
+    pub fn contiguous_bytes(&self, storage: &[u8], big_endian: bool) -> Result<Vec<u8>> {
+        let elem = self.dtype().size();
+        let out_of_bounds = || Error::from(ErrorKind::InvalidArgument("tensor data lies outside its storage".to_string()));
+
+        let numel = self.numel();
+        let mut data = if numel == 0 {
+            Vec::new()
+        }
+        else {
+            // Index of the furthest element, so the storage can be checked once.
+            let last = self
+                .shape
+                .iter()
+                .zip(self.strides.iter())
+                .try_fold(self.storage_offset, |last, (&dim, &stride)| last.checked_add((dim - 1).checked_mul(stride)?))
+                .and_then(|last| last.checked_add(1)?.checked_mul(elem as u64))
+                .filter(|&end| end <= storage.len() as u64)
+                .ok_or_else(out_of_bounds)?;
+
+            if self.is_contiguous() {
+                let start = self.storage_offset as usize * elem;
+                storage[start..last as usize].to_vec()
+            }
+            else {
+                // Zero strides can repeat a small storage many times, so a
+                // tiny file could otherwise ask for any amount of memory.
+                let max = (storage.len() as u64).saturating_mul(MAX_EXPANSION).max(MIN_TENSOR_CAP);
+                let len = numel.checked_mul(elem as u64).filter(|&len| len <= max).ok_or_else(|| Limit::TensorBytes.exceeded(max))?;
+                let mut data = Vec::with_capacity(len as usize);
+                let mut index = vec![0; self.shape.len()];
+
+                for _ in 0..numel {
+                    let pos = index
+                        .iter()
+                        .zip(self.strides.iter())
+                        .fold(self.storage_offset, |pos, (&i, &stride)| pos + i * stride) as usize;
+                    data.extend_from_slice(&storage[pos * elem..(pos + 1) * elem]);
+
+                    for (i, &dim) in index.iter_mut().zip(self.shape.iter()).rev() {
+                        *i += 1;
+                        if *i < dim {
+                            break;
+                        }
+                        *i = 0;
+                    }
+                }
+
+                data
+            }
+        };
+
+        if big_endian {
+            // Complex numbers are two floats, each swapped on its own.
+            let word = match self.dtype() {
+                DType::Complex64 | DType::Complex128 => elem / 2,
+                _ => elem,
+            };
+            data.chunks_exact_mut(word).for_each(<[u8]>::reverse);
+        }
+
+        Ok(data)
+    }
+
+    /// Whether the elements are laid out row-major with no gaps.
+    pub fn is_contiguous(&self) -> bool {
+        let mut expected = 1;
+        for (&dim, &stride) in self.shape.iter().zip(self.strides.iter()).rev() {
+            if dim != 1 && stride != expected {
+                return false;
+            }
+            expected = expected.saturating_mul(dim);
+        }
+
+        true
+    }
+}
+
+pub(crate) type Rebuild = fn(&Value) -> Option<Tensor>;
+
+/// Every tensor reachable through dicts, lists and tuples in `value`, keyed
+/// by the dotted path of dict keys and list indices leading to it.
+pub fn named_tensors(value: &Value) -> Vec<(String, &Tensor)> {
+    named_leaves(value)
+        .into_iter()
+        .filter_map(|(path, leaf)| Some((path, leaf.as_tensor()?)))
+        .collect()
+}
+
+/// Every value other than a dict, list or tuple reachable from `value`, keyed
+/// like [`named_tensors`]. Entries under keys that are not strings or ints
+/// are skipped, and objects with BUILD state, such as a `state_dict` carrying
+/// `_metadata`, are looked through to the object itself.
+pub fn named_leaves(value: &Value) -> Vec<(String, &Value)> {
+    let mut leaves = Vec::new();
+    let mut pending = vec![(String::new(), value)];
+
+    while let Some((path, value)) = pending.pop() {
+        let child = |key: String| if path.is_empty() { key } else { format!("{}.{}", path, key) };
+
+        let children: Vec<(String, &Value)> = match value {
+            Value::Dict(dict) => dict.iter().filter_map(|(k, v)| Some((child(key_name(k)?), v))).collect(),
+            Value::OrderedDict(dict) => dict.iter().filter_map(|(k, v)| Some((child(key_name(k)?), v))).collect(),
+            Value::List(items) | Value::Tuple(items) => {
+                items.iter().enumerate().map(|(i, v)| (child(i.to_string()), v)).collect()
+            }
+            Value::SetState(inst, _) => vec![(path.clone(), &**inst)],
+            leaf => {
+                leaves.push((path, leaf));
+                continue;
+            }
+        };
+
+        // Reversed so leaves come out in the order they were pickled.
+        pending.extend(children.into_iter().rev());
+    }
+
+    leaves
+}
+
+fn key_name(key: &Value) -> Option<String> {
+    match key {
+        Value::String(key) => Some(key.clone()),
+        Value::Int(key) => Some(key.to_string()),
+        _ => None,
+    }
+}
+
+/// Rebuild functions from `torch._utils` understood natively, by name.
+pub(crate) const REBUILD_FUNCTIONS: &[(&str, Rebuild)] = &[
+    ("_rebuild_tensor", rebuild_tensor),
+    ("_rebuild_tensor_v2", rebuild_tensor),
+    ("_rebuild_parameter", rebuild_parameter),
+    ("_rebuild_parameter_with_state", rebuild_parameter),
+];
+
+/// Runs a rebuild function, leaving the call inert if its arguments are not
+/// the storages and shapes we expect.
+pub(crate) fn call_rebuild(name: &'static str, rebuild: Rebuild, args: Value) -> Result<Value> {
+    match rebuild(&args) {
+        Some(tensor) => Ok(Value::Tensor(tensor)),
+        None => Ok(Value::Reduce(
+            Box::new(Value::Global(Global::new("torch._utils", name))),
+            Box::new(args),
+        )),
+    }
+}
+
+fn dims(value: &Value) -> Option<Vec<u64>> {
+    value.as_tuple()?.iter().map(Value::as_u64).collect()
+}
+
+/// `_rebuild_tensor(storage, storage_offset, size, stride)`, and the `_v2`
+/// variant which adds `requires_grad`, `backward_hooks` and `metadata`.
+fn rebuild_tensor(args: &Value) -> Option<Tensor> {
+    let [Value::Storage(storage), offset, shape, strides, rest @ ..] = args.as_tuple()? else {
+        return None;
+    };
+
+    let requires_grad = match rest.first() {
+        Some(requires_grad) => requires_grad.as_bool()?,
+        None => false,
+    };
+
+    let tensor = Tensor {
+        storage: storage.clone(),
+        storage_offset: offset.as_u64()?,
+        shape: dims(shape)?,
+        strides: dims(strides)?,
+        requires_grad,
+        parameter: false,
+    };
+
+    (tensor.shape.len() == tensor.strides.len()).then_some(tensor)
+}
+
+/// `_rebuild_parameter(data, requires_grad, backward_hooks)`, and the
+/// `_with_state` variant which adds python attributes we drop.
+fn rebuild_parameter(args: &Value) -> Option<Tensor> {
+    let [Value::Tensor(tensor), requires_grad, _, ..] = args.as_tuple()? else {
+        return None;
+    };
+
+    Some(Tensor {
+        requires_grad: requires_grad.as_bool()?,
+        parameter: true,
+        ..tensor.clone()
+    })
+}
@@ -0,0 +1,85 @@
+//! Runs the `dilligent` binary on each kind of input it accepts.
+
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+use common::LEGACY;
+
+/// `{'a': 1}` at protocol 2.
+const PICKLE: &[u8] = b"\x80\x02}q\x00X\x01\x00\x00\x00aq\x01K\x01s.";
+
+/// Runs `dilligent args.. <file holding input>`.
+fn run_on_file(args: &[&str], name: &str, input: &[u8]) -> Output {
+    let path = std::env::temp_dir().join(format!("dilligent-cli-{}-{}", std::process::id(), name));
+    std::fs::write(&path, input).unwrap();
+
+    let output = dilligent(args, Some(&path), None);
+    let _ = std::fs::remove_file(&path);
+    output
+}
+
+fn dilligent(args: &[&str], path: Option<&PathBuf>, stdin: Option<&[u8]>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_dilligent"));
+    command.args(args).args(path).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    let mut child = command.spawn().unwrap();
+    child.stdin.take().unwrap().write_all(stdin.unwrap_or_default()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn reads_bare_pickles() {
+    let out = stdout(&run_on_file(&["dump"], "model.pkl", PICKLE));
+    assert!(out.contains("\"a\""), "{out}");
+}
+
+#[test]
+fn reads_pickles_from_stdin() {
+    let out = stdout(&dilligent(&["dump", "-"], None, Some(PICKLE)));
+    assert!(out.contains("\"a\""), "{out}");
+
+    let out = stdout(&dilligent(&["tensors", "-"], None, Some(LEGACY)));
+    assert!(out.contains("bias"), "{out}");
+}
+
+#[test]
+fn reads_legacy_checkpoints() {
+    let out = stdout(&run_on_file(&["dump"], "legacy.pt", LEGACY));
+    assert!(out.contains("Found legacy checkpoint"), "{out}");
+}
+
+#[test]
+fn reads_zip_checkpoints() {
+    let mut model = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    model.start_file("archive/data.pkl", Default::default()).unwrap();
+    model.write_all(PICKLE).unwrap();
+    let model = model.finish().unwrap().into_inner();
+
+    let out = stdout(&run_on_file(&["dump"], "model.pt", &model));
+    assert!(out.contains("archive/data.pkl"), "{out}");
+}
+
+#[test]
+fn rejects_other_files() {
+    for (name, input) in [("notes.txt", &b"hello world\n"[..]), ("empty", b""), ("garbage", b"\xff\xfe\x00\x01")] {
+        let output = run_on_file(&["dump"], name, input);
+        assert!(!output.status.success(), "{name}");
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("is not a zip, legacy torch checkpoint or pickle"), "{name}: {stderr}");
+    }
+}
+
+#[test]
+fn bare_paths_are_dumped() {
+    let with_command = stdout(&run_on_file(&["dump"], "dump.pkl", PICKLE));
+    let without = stdout(&run_on_file(&[], "bare.pkl", PICKLE));
+    assert_eq!(without, with_command);
+}
@@ -0,0 +1,10 @@
+//! Fixtures shared by several test files.
+#![allow(dead_code)]
+
+/// `OrderedDict(weight=Parameter(2x3 float), bias=3 float)` as saved by
+/// `torch.save`, with storages pickled as persistent ids.
+pub const STATE_DICT: &[u8] = b"\x80\x02ccollections\nOrderedDict\nq\x00)Rq\x01(X\x06\x00\x00\x00weightq\x02ctorch._utils\n_rebuild_parameter\nq\x03ctorch._utils\n_rebuild_tensor_v2\nq\x04((X\x07\x00\x00\x00storageq\x05ctorch\nFloatStorage\nq\x06X\x01\x00\x00\x000q\x07X\x03\x00\x00\x00cpuq\x08K\x06tq\tQK\x00K\x02K\x03\x86q\nK\x03K\x01\x86q\x0b\x89h\x00)Rq\x0ctq\rRq\x0e\x88h\x00)Rq\x0f\x87q\x10Rq\x11X\x04\x00\x00\x00biasq\x12h\x04((h\x05h\x06X\x01\x00\x00\x001q\x13h\x08K\x03tq\x14QK\x00K\x03\x85q\x15K\x01\x85q\x16\x89h\x00)Rq\x17tq\x18Rq\x19u.";
+
+/// `torch.save({'bias': storage[1:3]}, f)` in the pre-1.6 format, from a
+/// big-endian machine, with a three float storage keyed `9`.
+pub const LEGACY: &[u8] = b"\x80\x02\x8a\nl\xfc\x9cF\xf9 j\xa8P\x19.\x80\x02M\xe9\x03.\x80\x02}q\x00(X\x10\x00\x00\x00protocol_versionq\x01M\xe9\x03X\x0d\x00\x00\x00little_endianq\x02\x89u.\x80\x02}q\x00X\x04\x00\x00\x00biasq\x01ctorch._utils\n_rebuild_tensor_v2\nq\x02((X\x07\x00\x00\x00storageq\x03ctorch\nFloatStorage\nq\x04X\x01\x00\x00\x009q\x05X\x03\x00\x00\x00cpuq\x06K\x03Ntq\x07QK\x01K\x02\x85q\x08K\x01\x85q\x09\x89ccollections\nOrderedDict\nq\n)Rq\x0btq\x0cRq\x0ds.\x80\x02]q\x00X\x01\x00\x00\x009q\x01a.\x00\x00\x00\x00\x00\x00\x00\x03?\x80\x00\x00@\x00\x00\x00@@\x00\x00";
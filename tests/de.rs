@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use dilligent::ErrorKind;
+use serde::Deserialize;
+
+/// A training config pickled by python at protocol 2, holding an
+/// `OrderedDict`, an integer over 64 bits and a `fractions.Fraction` call.
+const CONFIG: &[u8] = b"\x80\x02}q\x00(X\x04\x00\x00\x00nameq\x01X\x05\x00\x00\x00run-1q\x02X\x02\x00\x00\x00lrq\x03G?PbM\xd2\xf1\xa9\xfcX\x06\x00\x00\x00epochsq\x04K\nX\x05\x00\x00\x00betasq\x05G?\xec\xcc\xcc\xcc\xcc\xcc\xcdG?\xef\xf7\xce\xd9\x16\x87+\x86q\x06X\x06\x00\x00\x00layersq\x07]q\x08(K@K@eX\x04\x00\x00\x00seedq\tNX\x03\x00\x00\x00ampq\n\x88X\x08\x00\x00\x00scheduleq\x0bX\x06\x00\x00\x00cosineq\x0cX\x05\x00\x00\x00vocabq\rccollections\nOrderedDict\nq\x0e)Rq\x0f(X\x01\x00\x00\x00aq\x10K\x00X\x01\x00\x00\x00bq\x11K\x01uX\x03\x00\x00\x00bigq\x12\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00\x01X\x05\x00\x00\x00extraq\x13cfractions\nFraction\nq\x14K\x01K\x03\x86q\x15Rq\x16u.";
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Schedule {
+    Constant,
+    Cosine,
+}
+
+#[derive(Debug, Deserialize)]
+struct Config<'a> {
+    name: &'a str,
+    lr: f32,
+    epochs: u32,
+    betas: (f64, f64),
+    layers: Vec<usize>,
+    seed: Option<u64>,
+    amp: bool,
+    schedule: Schedule,
+    vocab: BTreeMap<String, u32>,
+    big: u128,
+}
+
+#[test]
+fn deserializes_structs() {
+    let value = dilligent::load(CONFIG).unwrap().unwrap();
+
+    // `extra` is ignored even though it could not be deserialized.
+    let config: Config = dilligent::from_value(&value).unwrap();
+    assert_eq!(config.name, "run-1");
+    assert_eq!(config.lr, 0.001);
+    assert_eq!(config.epochs, 10);
+    assert_eq!(config.betas, (0.9, 0.999));
+    assert_eq!(config.layers, [64, 64]);
+    assert_eq!(config.seed, None);
+    assert!(config.amp);
+    assert_eq!(config.schedule, Schedule::Cosine);
+    assert_eq!(config.vocab.into_iter().collect::<Vec<_>>(), [("a".to_string(), 0), ("b".to_string(), 1)]);
+    assert_eq!(config.big, 1 << 64);
+}
+
+#[test]
+fn rejects_unsupported_values() {
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Extra {
+        extra: (i64, i64),
+    }
+
+    let value = dilligent::load(CONFIG).unwrap().unwrap();
+
+    let err = dilligent::from_value::<Extra>(&value).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Unsupported(_)), "{err}");
+    assert!(err.to_string().contains("fractions.Fraction"), "{err}");
+
+    let err = dilligent::from_value::<Vec<u8>>(&value).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Deserialize(_)), "{err}");
+
+    let err = dilligent::from_value::<u32>(&dilligent::Value::Int(-1)).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Deserialize(_)), "{err}");
+}
@@ -0,0 +1,170 @@
+use num_bigint::BigInt;
+
+use dilligent::{ErrorKind, Op, OpCode, PickleReader};
+
+/// Decodes `pickle` up to its first error.
+fn first_error(pickle: &[u8]) -> dilligent::Error {
+    PickleReader::new(pickle).find_map(Result::err).unwrap()
+}
+
+#[test]
+fn ops_may_not_straddle_frames() {
+    // The frame holds BININT1's opcode but not its argument.
+    let err = first_error(b"\x80\x04\x95\x01\x00\x00\x00\x00\x00\x00\x00K\x01.");
+
+    assert!(matches!(err.kind(), ErrorKind::InvalidFrame(_)), "{err}");
+    assert_eq!(err.offset(), Some(11));
+    assert_eq!(err.opcode(), Some(OpCode::Binint1));
+}
+
+#[test]
+fn frames_may_not_outrun_the_input() {
+    let err = first_error(b"\x80\x04\x95\x10\x00\x00\x00\x00\x00\x00\x00K\x01.");
+
+    assert!(matches!(err.kind(), ErrorKind::InvalidFrame(_)), "{err}");
+    assert_eq!(err.offset(), Some(2));
+    assert_eq!(err.opcode(), Some(OpCode::Frame));
+}
+
+#[test]
+fn frames_may_not_start_inside_frames() {
+    let pickle = b"\x80\x04\x95\x0b\x00\x00\x00\x00\x00\x00\x00\x95\x01\x00\x00\x00\x00\x00\x00\x00..";
+    let err = first_error(pickle);
+
+    assert!(matches!(err.kind(), ErrorKind::InvalidFrame(_)), "{err}");
+    assert_eq!(err.offset(), Some(11));
+    assert_eq!(err.opcode(), Some(OpCode::Frame));
+}
+
+#[test]
+fn ops_follow_frames() {
+    let ops: Vec<Op> = PickleReader::new(&b"\x80\x04\x95\x03\x00\x00\x00\x00\x00\x00\x00K\x01."[..])
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    assert!(matches!(ops[..], [Op::Proto(4), Op::Frame(3), Op::BinInt1(1), Op::Stop]), "{ops:?}");
+}
+
+/// The single op a protocol 0 line decodes to.
+fn op(line: &[u8]) -> dilligent::Result<Op> {
+    PickleReader::new(line).next().unwrap()
+}
+
+#[test]
+fn int_falls_back_to_long() {
+    assert!(matches!(op(b"I42\n"), Ok(Op::Int(42))));
+    assert!(matches!(op(b"I-9223372036854775808\n"), Ok(Op::Int(i64::MIN))));
+    assert!(matches!(op(b"I01\n"), Ok(Op::True)));
+
+    let big: BigInt = "-18446744073709551616".parse().unwrap();
+    assert!(matches!(op(b"I-18446744073709551616\n"), Ok(Op::Long(value)) if value == big));
+    assert!(matches!(op(b"I9223372036854775808\n"), Ok(Op::Long(value)) if value == BigInt::from(1u64 << 63)));
+
+    let value = dilligent::load(&b"I123456789012345678901234567890\n."[..]).unwrap().unwrap();
+    assert_eq!(value.as_bigint(), Some("123456789012345678901234567890".parse().unwrap()));
+
+    assert!(matches!(op(b"I12x\n").unwrap_err().kind(), ErrorKind::InvalidArgument(_)));
+}
+
+fn string(line: &[u8]) -> dilligent::Result<Vec<u8>> {
+    match op(line)? {
+        Op::String(data) => Ok(data),
+        other => panic!("{other:?}"),
+    }
+}
+
+fn unicode(line: &[u8]) -> dilligent::Result<String> {
+    match op(line)? {
+        Op::Unicode(text) => Ok(text),
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn string_escapes_match_python() {
+    // Expected values from `codecs.escape_decode`.
+    assert_eq!(string(b"S'\\x41\\x7e\\xff'\n").unwrap(), b"A~\xff");
+    assert_eq!(string(b"S'\\101\\0\\12\\777'\n").unwrap(), b"A\x00\n\xff");
+    assert_eq!(string(b"S'\\1234'\n").unwrap(), b"S4");
+    assert_eq!(string(b"S'\\a\\b\\f\\n\\r\\t\\v\\\\\\'\\\"'\n").unwrap(), b"\x07\x08\x0c\n\r\t\x0b\\'\"");
+    assert_eq!(string(b"S\"it's\"\n").unwrap(), b"it's");
+    // Unknown escapes are kept as they are.
+    assert_eq!(string(b"S'\\q\\8'\n").unwrap(), b"\\q\\8");
+
+    for bad in [&b"S'\\x4'\n"[..], b"S'\\x+1'\n", b"S'\\xgg'\n", b"S'abc\\'\n", b"S'abc\n", b"Sabc\n", b"S'abc\"\n"] {
+        let err = string(bad).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)), "{bad:?}: {err}");
+    }
+}
+
+#[test]
+fn unicode_escapes_match_python() {
+    // Expected values from `codecs.decode(..., 'raw-unicode-escape')`.
+    assert_eq!(unicode(b"V\\u00e9\\U0001f600\n").unwrap(), "\u{e9}\u{1f600}");
+    assert_eq!(unicode(b"Vcaf\xe9\n").unwrap(), "caf\u{e9}");
+    // Only `\u` and `\U` are escapes, after an odd number of backslashes.
+    assert_eq!(unicode(b"V\\x41\\n\\101\n").unwrap(), "\\x41\\n\\101");
+    assert_eq!(unicode(b"V\\\\u0041\n").unwrap(), "\\\\u0041");
+    assert_eq!(unicode(b"V\\\\\\u0041\n").unwrap(), "\\\\A");
+
+    for bad in [&b"V\\u12\n"[..], b"V\\U0011000\n", b"V\\U00110000\n", b"V\\ud800\n", b"V\\u+123\n"] {
+        let err = unicode(bad).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)), "{bad:?}: {err}");
+    }
+}
+
+#[test]
+fn errors_carry_the_failing_op() {
+    let err = dilligent::load(&b"\x80\x02NK"[..]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Truncated), "{err}");
+    assert_eq!((err.offset(), err.opcode()), (Some(3), Some(OpCode::Binint1)));
+
+    let err = dilligent::load(&b"\x80\x02N\xff."[..]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnknownOpcode(0xff)), "{err}");
+    assert_eq!((err.offset(), err.opcode()), (Some(3), None));
+
+    let err = dilligent::load(&b"\x80\x02Nq\x00h\x05."[..]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::MemoMiss(5)), "{err}");
+    assert_eq!((err.offset(), err.opcode()), (Some(5), Some(OpCode::Binget)));
+}
+
+#[test]
+fn interpreter_errors_carry_no_offset() {
+    let mut interp = dilligent::Interpreter::new();
+
+    let err = interp.exec_op(Op::BinGet(5)).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::MemoMiss(5)), "{err}");
+    assert_eq!((err.offset(), err.opcode()), (None, None));
+
+    let err = err.at(7, Some(OpCode::Binget));
+    assert_eq!((err.offset(), err.opcode()), (Some(7), Some(OpCode::Binget)));
+}
+
+#[test]
+fn decodes_binary_opcodes() {
+    let pickle = b"02G?\xf8\x00\x00\x00\x00\x00\x00\x8a\x02\x00\x01\x8b\x01\x00\x00\x00\xff\x8c\x01aB\x02\x00\x00\x00xy\x94\x93\x92\x97\x98\x96\x02\x00\x00\x00\x00\x00\x00\x00ab.";
+    let ops: Vec<Op> = PickleReader::new(&pickle[..]).collect::<Result<_, _>>().unwrap();
+
+    assert!(
+        matches!(
+            &ops[..],
+            [
+                Op::Pop,
+                Op::Dup,
+                Op::BinFloat(1.5),
+                Op::Long1(long1),
+                Op::Long4(long4),
+                Op::ShortBinunicode(text),
+                Op::BinBytes(bytes),
+                Op::Memoize,
+                Op::StackGlobal,
+                Op::NewObjEx,
+                Op::NextBuffer,
+                Op::ReadonlyBuffer,
+                Op::ByteArray8(bytearray),
+                Op::Stop,
+            ] if *long1 == BigInt::from(256) && *long4 == BigInt::from(-1) && text == "a" && bytes == b"xy" && bytearray == b"ab"
+        ),
+        "{ops:?}"
+    );
+}
@@ -0,0 +1,55 @@
+use dilligent::PickleReader;
+
+/// `{'a': [1, 'x'], 'b': (b'\x00', 2.5)}` as pickled by protocol 4.
+const DICT: &[u8] = b"\x80\x04\x95&\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x01a\x94]\x94(K\x01\x8c\x01x\x94e\x8c\x01b\x94C\x01\x00\x94G@\x04\x00\x00\x00\x00\x00\x00\x86\x94u.";
+
+/// `python -m pickletools` on [`DICT`].
+const DICT_DIS: &str = r"    0: \x80 PROTO      4
+    2: \x95 FRAME      38
+   11: }    EMPTY_DICT
+   12: \x94 MEMOIZE    (as 0)
+   13: (    MARK
+   14: \x8c     SHORT_BINUNICODE 'a'
+   17: \x94     MEMOIZE    (as 1)
+   18: ]        EMPTY_LIST
+   19: \x94     MEMOIZE    (as 2)
+   20: (        MARK
+   21: K            BININT1    1
+   23: \x8c         SHORT_BINUNICODE 'x'
+   26: \x94         MEMOIZE    (as 3)
+   27: e            APPENDS    (MARK at 20)
+   28: \x8c     SHORT_BINUNICODE 'b'
+   31: \x94     MEMOIZE    (as 4)
+   32: C        SHORT_BINBYTES b'\x00'
+   35: \x94     MEMOIZE    (as 5)
+   36: G        BINFLOAT   2.5
+   45: \x86     TUPLE2
+   46: \x94     MEMOIZE    (as 6)
+   47: u        SETITEMS   (MARK at 13)
+   48: .    STOP
+highest protocol among opcodes = 4
+";
+
+#[test]
+fn matches_pickletools() {
+    let mut out = Vec::new();
+    dilligent::dis(&mut PickleReader::new(DICT), &mut out, false).unwrap();
+
+    assert_eq!(String::from_utf8(out).unwrap(), DICT_DIS);
+}
+
+#[test]
+fn tracks_stack_depth() {
+    let mut out = Vec::new();
+    dilligent::dis(&mut PickleReader::new(DICT), &mut out, true).unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    let depths: Vec<&str> = out
+        .lines()
+        .filter_map(|line| line.rsplit_once(" depth ").map(|(_, depth)| depth))
+        .collect();
+
+    // After the inner APPENDS the dict and key are set aside by the outer MARK.
+    assert_eq!(depths[13], "3");
+    assert_eq!(depths.last(), Some(&"0"));
+}
@@ -0,0 +1,181 @@
+use num_bigint::BigInt;
+
+use dilligent::{ErrorKind, Value};
+
+#[test]
+fn loads_values_with_accessors() {
+    // `{'a': 1, 'b': [1.5, None, True], 'c': (b'x', 'y'), 'd': {2},
+    // 'e': frozenset({3}), 'f': 2**70}` at protocols 4 and 0.
+    let pickles: [&[u8]; 2] = [
+        b"\x80\x04\x95A\x00\x00\x00\x00\x00\x00\x00}(\x8c\x01aK\x01\x8c\x01b](G?\xf8\x00\x00\x00\x00\x00\x00N\x88e\x8c\x01cC\x01x\x8c\x01y\x86\x8c\x01d\x8f(K\x02\x90\x8c\x01e(K\x03\x91\x8c\x01f\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00@u.",
+        b"(dp0\nVa\np1\nI1\nsVb\np2\n(lp3\nF1.5\naNaI01\nasVc\np4\n(c_codecs\nencode\np5\n(Vx\np6\nVlatin1\np7\ntp8\nRp9\nVy\np10\ntp11\nsVd\np12\nc__builtin__\nset\np13\n((lp14\nI2\natp15\nRp16\nsVe\np17\nc__builtin__\nfrozenset\np18\n((lp19\nI3\natp20\nRp21\nsVf\np22\nL1180591620717411303424L\ns.",
+    ];
+
+    for pickle in pickles {
+        let value = dilligent::load(pickle).unwrap().unwrap();
+        let dict = value.as_dict().unwrap();
+        assert_eq!(dict.len(), 6);
+
+        assert_eq!(dict.get("a").and_then(Value::as_i64), Some(1));
+
+        let list = dict.get("b").and_then(Value::as_list).unwrap();
+        assert_eq!(list[0].as_f64(), Some(1.5));
+        assert!(list[1].is_none());
+        assert_eq!(list[2].as_bool(), Some(true));
+
+        let tuple = dict.get("c").and_then(Value::as_tuple).unwrap();
+        assert_eq!(tuple[0].as_bytes(), Some(&b"x"[..]));
+        assert_eq!(tuple[1].as_str(), Some("y"));
+
+        let set = dict.get("d").and_then(Value::as_set).unwrap();
+        assert_eq!(set.iter().map(Value::as_i64).collect::<Vec<_>>(), [Some(2)]);
+        assert!(dict.get("d").and_then(Value::as_frozenset).is_none());
+
+        let frozenset = dict.get("e").and_then(Value::as_frozenset).unwrap();
+        assert_eq!(frozenset.iter().map(Value::as_i64).collect::<Vec<_>>(), [Some(3)]);
+        assert!(dict.get("e").and_then(Value::as_set).is_none());
+
+        assert!(matches!(dict.get("f"), Some(Value::BigInt(value)) if *value == BigInt::from(1u128 << 70)));
+    }
+}
+
+#[test]
+fn reads_integers_at_their_width() {
+    // `(255, 65535, -1, -32768, 0, 32767, 2**63 - 1, -2**63, 2**64)`, the
+    // first two as BININT1 and BININT2 and the rest as LONG1 and LONG4.
+    let pickle = b"\x80\x02(K\xffM\xff\xff\x8a\x01\xff\x8a\x02\x00\x80\x8a\x00\x8b\x02\x00\x00\x00\xff\x7f\x8a\x08\xff\xff\xff\xff\xff\xff\xff\x7f\x8a\t\x00\x00\x00\x00\x00\x00\x00\x80\xff\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00\x01t.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    let items = value.as_tuple().unwrap();
+
+    // BININT1 and BININT2 are unsigned.
+    assert!(matches!(items[0], Value::Int(255)), "{items:?}");
+    assert!(matches!(items[1], Value::Int(65535)), "{items:?}");
+
+    // Longs that fit an i64 are plain integers, whatever their width.
+    assert!(matches!(items[2], Value::Int(-1)), "{items:?}");
+    assert!(matches!(items[3], Value::Int(-32768)), "{items:?}");
+    assert!(matches!(items[4], Value::Int(0)), "{items:?}");
+    assert!(matches!(items[5], Value::Int(32767)), "{items:?}");
+    assert!(matches!(items[6], Value::Int(i64::MAX)), "{items:?}");
+    assert!(matches!(items[7], Value::Int(i64::MIN)), "{items:?}");
+    assert!(matches!(&items[8], Value::BigInt(value) if *value == BigInt::from(1u128 << 64)), "{items:?}");
+
+    assert_eq!(items[6].as_u64(), Some(i64::MAX as u64));
+    assert_eq!(items[8].as_i64(), None);
+    assert_eq!(items[8].as_u64(), None);
+}
+
+#[test]
+fn negative_integers_have_no_u64() {
+    assert_eq!(Value::Int(-1).as_u64(), None);
+    assert_eq!(Value::BigInt(BigInt::from(-1)).as_u64(), None);
+    assert_eq!(Value::from(-BigInt::from(1u128 << 64)).as_u64(), None);
+    assert_eq!(Value::Int(-1).as_i64(), Some(-1));
+    assert_eq!(Value::BigInt(BigInt::from(u64::MAX)).as_u64(), Some(u64::MAX));
+}
+
+#[test]
+fn builds_sets_and_byte_strings() {
+    // `(set(), {1, 2}, set(), frozenset({3}), frozenset(), bytearray(b'ab'),
+    // bytearray(), None, 0.5, b'x', b'y')` at protocol 5, the third set
+    // given an empty ADDITEMS.
+    let pickle = b"\x80\x05(\x8f\x8f(K\x01K\x02\x90\x8f(\x90(K\x03\x91(\x91\x96\x02\x00\x00\x00\x00\x00\x00\x00ab\x96\x00\x00\x00\x00\x00\x00\x00\x00NG?\xe0\x00\x00\x00\x00\x00\x00C\x01xB\x01\x00\x00\x00yt.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    let items = value.as_tuple().unwrap();
+
+    assert!(matches!(&items[0], Value::Set(items) if items.is_empty()), "{items:?}");
+    assert!(matches!(&items[1], Value::Set(items) if items.iter().map(Value::as_i64).eq([Some(1), Some(2)])), "{items:?}");
+    assert!(matches!(&items[2], Value::Set(items) if items.is_empty()), "{items:?}");
+    assert!(matches!(&items[3], Value::FrozenSet(items) if items.iter().map(Value::as_i64).eq([Some(3)])), "{items:?}");
+    assert!(matches!(&items[4], Value::FrozenSet(items) if items.is_empty()), "{items:?}");
+    assert!(matches!(&items[5], Value::ByteArray(data) if data == b"ab"), "{items:?}");
+    assert!(matches!(&items[6], Value::ByteArray(data) if data.is_empty()), "{items:?}");
+    assert!(matches!(items[7], Value::None), "{items:?}");
+    assert!(matches!(items[8], Value::Float(value) if value == 0.5), "{items:?}");
+    assert!(matches!(&items[9], Value::Bytes(data) if data == b"x"), "{items:?}");
+    assert!(matches!(&items[10], Value::Bytes(data) if data == b"y"), "{items:?}");
+}
+
+#[test]
+fn adds_items_only_to_sets() {
+    for pickle in [&b"\x80\x04](K\x01\x90."[..], b"\x80\x04(K\x01\x91(K\x02\x90."] {
+        let err = dilligent::load(pickle).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::TypeMismatch(_)), "{err}");
+    }
+}
+
+#[test]
+fn python_2_strings_that_are_not_text_load_as_bytes() {
+    // `('\xff\x00', 'ab')` as python 2 writes it at protocols 0, 1 and 2, with
+    // STRING, BINSTRING and SHORT_BINSTRING, and a STRING holding the raw
+    // bytes rather than escapes.
+    let pickles: [&[u8]; 4] = [
+        b"(S'\\xff\\x00'\np0\nS'ab'\np1\ntp2\n.",
+        b"(T\x02\x00\x00\x00\xff\x00T\x02\x00\x00\x00abt.",
+        b"\x80\x02U\x02\xff\x00U\x02ab\x86.",
+        b"(S'\xff\x00'\np0\nS'ab'\np1\ntp2\n.",
+    ];
+
+    for pickle in pickles {
+        let value = dilligent::load(pickle).unwrap().unwrap();
+        let items = value.as_tuple().unwrap();
+        assert!(matches!(&items[0], Value::Bytes(data) if data == b"\xff\x00"), "{value:?}");
+        assert_eq!(items[1].as_str(), Some("ab"));
+        assert!(dilligent::is_pickle(pickle), "{pickle:?}");
+    }
+}
+
+#[test]
+fn setting_an_equal_key_replaces_its_value() {
+    // `{1: 'a', True: 'b', 1.0: 'c', (1,): 2, (True,): 3}` with 'k' then set
+    // to 1 and 2 by SETITEM, which python loads as `{1: 'c', (1,): 3, 'k': 2}`.
+    let pickle = b"\x80\x02}(K\x01X\x01\x00\x00\x00a\x88X\x01\x00\x00\x00bG?\xf0\x00\x00\x00\x00\x00\x00X\x01\x00\x00\x00cK\x01\x85K\x02\x88\x85K\x03uX\x01\x00\x00\x00kK\x01sX\x01\x00\x00\x00kK\x02s.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    let items: Vec<_> = value.as_dict().unwrap().iter().collect();
+
+    assert_eq!(items.len(), 3, "{value:?}");
+    assert!(matches!(items[0], (Value::Int(1), Value::String(value)) if value == "c"), "{value:?}");
+    assert!(matches!(items[1], (Value::Tuple(key), Value::Int(3)) if key[0].as_i64() == Some(1)), "{value:?}");
+    assert!(matches!(items[2], (Value::String(key), Value::Int(2)) if key == "k"), "{value:?}");
+
+    // `OrderedDict` keeps a replaced key where it was first set.
+    let pickle = b"\x80\x02ccollections\nOrderedDict\n)R(X\x01\x00\x00\x00aK\x01X\x01\x00\x00\x00bK\x02X\x01\x00\x00\x00aK\x03u.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    let items: Vec<_> = value.as_ordered_dict().unwrap().iter().map(|(k, v)| (k.as_str(), v.as_i64())).collect();
+    assert_eq!(items, [(Some("a"), Some(3)), (Some("b"), Some(2))]);
+}
+
+#[test]
+fn integral_floats_are_the_same_key_as_ints() {
+    // `{2**60: 1, 2.0**60: 2, 2**64: 3, 2.0**64: 4}`.
+    let pickle = b"\x80\x02}(\x8a\x08\x00\x00\x00\x00\x00\x00\x00\x10K\x01GC\xb0\x00\x00\x00\x00\x00\x00K\x02\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00\x01K\x03GC\xf0\x00\x00\x00\x00\x00\x00K\x04u.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    let values: Vec<_> = value.as_dict().unwrap().iter().map(|(_, v)| v.as_i64()).collect();
+
+    assert_eq!(values, [Some(2), Some(4)], "{value:?}");
+}
+
+#[test]
+fn opaque_keys_are_never_equal() {
+    // `{f(): 1, f(): 2}`, where each call may return a distinct object.
+    let pickle = b"\x80\x02}(cm\nf\n)RK\x01cm\nf\n)RK\x02u.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+
+    assert_eq!(value.as_dict().map(|dict| dict.len()), Some(2), "{value:?}");
+}
+
+#[test]
+fn sets_hold_equal_members_once() {
+    // `({1}, {1, 2}, frozenset({1}), {2})`: the first set added 1 twice, the
+    // second added 1 again in a later ADDITEMS, the frozenset was built from
+    // `1, True, 1.0` and the last set from `set([2, 2])`.
+    let pickle = b"\x80\x04(\x8f(K\x01K\x01\x90\x8f(K\x01\x90(K\x01K\x02\x90(K\x01\x88G?\xf0\x00\x00\x00\x00\x00\x00\x91cbuiltins\nset\n](K\x02K\x02e\x85Rt.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    let items = value.as_tuple().unwrap();
+    let members = |set: &dilligent::Set| set.iter().map(Value::as_i64).collect::<Vec<_>>();
+
+    assert_eq!(items[0].as_set().map(members), Some(vec![Some(1)]), "{value:?}");
+    assert_eq!(items[1].as_set().map(members), Some(vec![Some(1), Some(2)]), "{value:?}");
+    assert_eq!(items[2].as_frozenset().map(members), Some(vec![Some(1)]), "{value:?}");
+    assert_eq!(items[3].as_set().map(members), Some(vec![Some(2)]), "{value:?}");
+}
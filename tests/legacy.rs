@@ -0,0 +1,96 @@
+use std::io::Cursor;
+
+use dilligent::{DType, ErrorKind, LegacyCheckpoint, Op, Value};
+
+mod common;
+use common::LEGACY;
+
+#[test]
+fn detects_legacy_checkpoints() {
+    assert!(dilligent::is_legacy_checkpoint(LEGACY));
+    assert!(!dilligent::is_legacy_checkpoint(b"PK\x03\x04"));
+    assert!(!dilligent::is_legacy_checkpoint(b"\x80\x02}q\x00."));
+}
+
+#[test]
+fn reads_tensors_and_storage_ranges() {
+    let checkpoint = LegacyCheckpoint::read(&mut Cursor::new(LEGACY)).unwrap();
+    assert!(!checkpoint.little_endian);
+
+    let bias = checkpoint.value.as_dict().and_then(|d| d.get("bias")).and_then(Value::as_tensor).unwrap();
+    assert_eq!(bias.dtype(), DType::F32);
+    assert_eq!(bias.shape, vec![2]);
+    assert_eq!(bias.storage_offset, 1);
+    assert_eq!(bias.storage.key, "9");
+
+    let range = checkpoint.storage_range("9").unwrap();
+    assert_eq!(range.end, LEGACY.len() as u64);
+    assert_eq!(range.end - range.start, 12);
+
+    let storage = &LEGACY[range.start as usize..range.end as usize];
+    let data = bias.contiguous_bytes(storage, true).unwrap();
+    assert_eq!(data, [2.0f32.to_le_bytes(), 3.0f32.to_le_bytes()].concat());
+}
+
+#[test]
+fn truncated_storages_are_an_error() {
+    let truncated = &LEGACY[..LEGACY.len() - 1];
+
+    assert!(LegacyCheckpoint::read(&mut Cursor::new(truncated)).is_err());
+}
+
+#[test]
+fn writes_storages_back_with_view_metadata() {
+    let checkpoint = LegacyCheckpoint::read(&mut Cursor::new(LEGACY)).unwrap();
+
+    let mut pickle = Vec::new();
+    dilligent::dump(&checkpoint.value, &mut pickle, 2).unwrap();
+
+    // torch unpacks `(typename, storage_type, root_key, location, numel,
+    // view_metadata)` from legacy persistent ids.
+    let ops: Vec<Op> = dilligent::PickleReader::new(&pickle[..]).collect::<Result<_, _>>().unwrap();
+    let pid_end = ops.iter().position(|op| matches!(op, Op::BinPersId)).unwrap();
+    assert!(matches!(ops[pid_end - 2..pid_end], [Op::None, Op::Tuple]), "{:?}", &ops[..pid_end]);
+    let pid_start = ops[..pid_end].iter().rposition(|op| matches!(op, Op::Mark)).unwrap();
+    let items = ops[pid_start + 1..pid_end - 1]
+        .iter()
+        .filter(|op| !matches!(op, Op::BInput(_)))
+        .count();
+    assert_eq!(items, 6);
+
+    let again = dilligent::load(&pickle[..]).unwrap().unwrap();
+    assert_eq!(dilligent::named_tensors(&again), dilligent::named_tensors(&checkpoint.value));
+}
+
+#[test]
+fn sizes_storages_inside_reduced_objects() {
+    // `torch.save(model)` pickles the module itself, so the tensor sits in a
+    // REDUCE's arguments: `model.Net({'bias': tensor})`.
+    let object_start = LEGACY.windows(7).position(|w| w == b"\x80\x02}q\x00X\x04").unwrap();
+    let object_end = object_start + LEGACY[object_start..].windows(2).position(|w| w == b"s.").unwrap() + 1;
+    let legacy = [
+        &LEGACY[..object_start + 2],
+        b"cmodel\nNet\n",
+        &LEGACY[object_start + 2..object_end],
+        b"\x85R",
+        &LEGACY[object_end..],
+    ]
+    .concat();
+
+    let checkpoint = LegacyCheckpoint::read(&mut Cursor::new(&legacy)).unwrap();
+    assert!(matches!(checkpoint.value, Value::Reduce(..)), "{:?}", checkpoint.value);
+    assert!(dilligent::named_tensors(&checkpoint.value).is_empty());
+
+    let range = checkpoint.storage_range("9").unwrap();
+    assert_eq!(range.end, legacy.len() as u64);
+    assert_eq!(range.end - range.start, 12);
+}
+
+#[test]
+fn truncated_object_pickles_keep_their_error() {
+    let object_start = LEGACY.windows(7).position(|w| w == b"\x80\x02}q\x00X\x04").unwrap();
+    let truncated = &LEGACY[..object_start + 40];
+
+    let err = LegacyCheckpoint::read(&mut Cursor::new(truncated)).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Truncated), "{err}");
+}
@@ -0,0 +1,249 @@
+//! Feeds corrupt and arbitrary byte sequences through the decoder and the
+//! interpreter. Any input is allowed to be rejected, none may panic.
+
+use dilligent::{load, ErrorKind, Interpreter, InterpreterLimits, Limit, PickleReader};
+
+/// The same object pickled at protocols 0, 2, 4 and 5.
+const VALID_PICKLES: &[&[u8]] = &[
+    b"(dp0\nVa\np1\n(lp2\nI1\naI-2\na(I3\nF4.5\ntp3\nasVb\np4\nVhi\\u1234\np5\
+      \nsVc\np6\nI01\nsVn\np7\nNsVo\np8\nccollections\nOrderedDict\np9\n(tRp10\
+      \nVx\np11\nL1180591620717411303424L\nssVs\np12\nc__builtin__\nset\np13\n\
+      ((lp14\nI1\natp15\nRp16\nsVf\np17\nc__builtin__\nfrozenset\np18\n((lp19\
+      \nI2\natp20\nRp21\nsVy\np22\nc_codecs\nencode\np23\n(Vxy\np24\nVlatin1\n\
+      p25\ntp26\nRp27\nsVz\np28\nc__builtin__\nbytearray\np29\n(g23\n(Vq\np30\
+      \ng25\ntp31\nRp32\ntp33\nRp34\nsVr\np35\ng2\ns.",
+    b"\x80\x02}q\x00(X\x01\x00\x00\x00aq\x01]q\x02(K\x01J\xfe\xff\xff\xffK\x03\
+      G@\x12\x00\x00\x00\x00\x00\x00\x86q\x03eX\x01\x00\x00\x00bq\x04X\x05\x00\
+      \x00\x00hi\xe1\x88\xb4q\x05X\x01\x00\x00\x00cq\x06\x88X\x01\x00\x00\x00n\
+      q\x07NX\x01\x00\x00\x00oq\x08ccollections\nOrderedDict\nq\x09)Rq\nX\x01\
+      \x00\x00\x00xq\x0b\x8a\x09\x00\x00\x00\x00\x00\x00\x00\x00@sX\x01\x00\
+      \x00\x00sq\x0cc__builtin__\nset\nq\x0d]q\x0eK\x01a\x85q\x0fRq\x10X\x01\
+      \x00\x00\x00fq\x11c__builtin__\nfrozenset\nq\x12]q\x13K\x02a\x85q\x14Rq\
+      \x15X\x01\x00\x00\x00yq\x16c_codecs\nencode\nq\x17X\x02\x00\x00\x00xyq\
+      \x18X\x06\x00\x00\x00latin1q\x19\x86q\x1aRq\x1bX\x01\x00\x00\x00zq\x1cc_\
+      _builtin__\nbytearray\nq\x1dh\x17X\x01\x00\x00\x00qq\x1eh\x19\x86q\x1fRq\
+      \x20\x85q!Rq\"X\x01\x00\x00\x00rq#h\x02u.",
+    b"\x80\x04\x95\xb3\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x01a\x94]\x94(K\
+      \x01J\xfe\xff\xff\xffK\x03G@\x12\x00\x00\x00\x00\x00\x00\x86\x94e\x8c\
+      \x01b\x94\x8c\x05hi\xe1\x88\xb4\x94\x8c\x01c\x94\x88\x8c\x01n\x94N\x8c\
+      \x01o\x94\x8c\x0bcollections\x94\x8c\x0bOrderedDict\x94\x93\x94)R\x94\
+      \x8c\x01x\x94\x8a\x09\x00\x00\x00\x00\x00\x00\x00\x00@s\x8c\x01s\x94\x8f\
+      \x94(K\x01\x90\x8c\x01f\x94(K\x02\x91\x94\x8c\x01y\x94C\x02xy\x94\x8c\
+      \x01z\x94\x8c\x08builtins\x94\x8c\x09bytearray\x94\x93\x94C\x01q\x94\x85\
+      \x94R\x94\x8c\x01r\x94h\x02u.",
+    b"\x80\x05\x95\x9d\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x01a\x94]\x94(K\
+      \x01J\xfe\xff\xff\xffK\x03G@\x12\x00\x00\x00\x00\x00\x00\x86\x94e\x8c\
+      \x01b\x94\x8c\x05hi\xe1\x88\xb4\x94\x8c\x01c\x94\x88\x8c\x01n\x94N\x8c\
+      \x01o\x94\x8c\x0bcollections\x94\x8c\x0bOrderedDict\x94\x93\x94)R\x94\
+      \x8c\x01x\x94\x8a\x09\x00\x00\x00\x00\x00\x00\x00\x00@s\x8c\x01s\x94\x8f\
+      \x94(K\x01\x90\x8c\x01f\x94(K\x02\x91\x94\x8c\x01y\x94C\x02xy\x94\x8c\
+      \x01z\x94\x96\x01\x00\x00\x00\x00\x00\x00\x00q\x94\x8c\x01r\x94h\x02u.",
+];
+
+/// A small deterministic xorshift generator, so failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[test]
+fn valid_pickles_load() {
+    for pickle in VALID_PICKLES {
+        let value = load(&pickle[..]).unwrap().unwrap();
+        assert!(value.as_dict().is_some());
+    }
+}
+
+#[test]
+fn every_two_byte_sequence() {
+    for a in 0..=u8::MAX {
+        for b in 0..=u8::MAX {
+            let _ = load(&[a, b][..]);
+        }
+    }
+}
+
+#[test]
+fn every_truncation_of_valid_pickles() {
+    for pickle in VALID_PICKLES {
+        for len in 0..pickle.len() {
+            let _ = load(&pickle[..len]);
+        }
+    }
+}
+
+#[test]
+fn random_single_byte_mutations_of_valid_pickles() {
+    let mut rng = Rng(0xd1b5_4a32_d192_ed03);
+    let mut buf = Vec::new();
+
+    for _ in 0..10_000 {
+        let pickle = VALID_PICKLES[rng.below(VALID_PICKLES.len() as u64) as usize];
+        buf.clear();
+        buf.extend_from_slice(pickle);
+        buf[rng.below(pickle.len() as u64) as usize] = rng.below(256) as u8;
+        let _ = load(&buf[..]);
+    }
+}
+
+/// Takes over half a minute in debug builds, the fuzz targets cover the same
+/// ground.
+#[test]
+#[ignore]
+fn every_single_byte_mutation_of_valid_pickles() {
+    let mut buf = Vec::new();
+
+    for pickle in VALID_PICKLES {
+        for pos in 0..pickle.len() {
+            for byte in 0..=u8::MAX {
+                buf.clear();
+                buf.extend_from_slice(pickle);
+                buf[pos] = byte;
+                let _ = load(&buf[..]);
+            }
+        }
+    }
+}
+
+#[test]
+fn random_opcode_sequences() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut buf = Vec::new();
+
+    for _ in 0..50_000 {
+        buf.clear();
+        let len = rng.below(48);
+        for _ in 0..len {
+            // Favour bytes that are opcodes, but keep the argument bytes random.
+            let byte = match rng.below(4) {
+                0 => rng.below(256) as u8,
+                _ => b"(.012FIJKLMNPQRSTUVXabcdegh}ijl]opqrstu)G"[rng.below(41) as usize],
+            };
+            buf.push(byte);
+        }
+        buf.push(b'.');
+        let _ = load(&buf[..]);
+    }
+}
+
+#[test]
+fn random_splices_of_valid_pickles() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut buf = Vec::new();
+
+    for _ in 0..10_000 {
+        let a = VALID_PICKLES[rng.below(VALID_PICKLES.len() as u64) as usize];
+        let b = VALID_PICKLES[rng.below(VALID_PICKLES.len() as u64) as usize];
+        let cut_a = rng.below(a.len() as u64) as usize;
+        let cut_b = rng.below(b.len() as u64) as usize;
+
+        buf.clear();
+        buf.extend_from_slice(&a[..cut_a]);
+        buf.extend_from_slice(&b[cut_b..]);
+        let _ = load(&buf[..]);
+    }
+}
+
+#[test]
+fn stack_underflow_is_an_error() {
+    for pickle in [&b"R."[..], b"K\x01R.", b"s.", b"b.", b"a.", b"\x85.", b"\x87.", b"Q.", b"\x93.", b"2.", b"."] {
+        let err = load(pickle).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::StackUnderflow), "{pickle:?}: {err}");
+    }
+}
+
+#[test]
+fn missing_mark_is_an_error() {
+    for pickle in [&b"t."[..], b"l.", b"d.", b"1.", b"0.", b"}u.", b"]e.", b"\x8f\x90.", b"\x91.", b"o."] {
+        let err = load(pickle).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::MissingMark), "{pickle:?}: {err}");
+    }
+}
+
+#[test]
+fn huge_declared_lengths_exceed_limits() {
+    for pickle in [&b"X\xff\xff\xff\xff"[..], b"\x8d\xff\xff\xff\xff\xff\xff\xff\x7f", b"B\xff\xff\xff\xff"] {
+        let err = load(pickle).unwrap_err();
+        assert!(
+            matches!(err.kind(), ErrorKind::LimitExceeded { limit: Limit::PayloadLen, .. }),
+            "{pickle:?}: {err}"
+        );
+    }
+}
+
+#[test]
+fn nested_marks_exceed_limits() {
+    let pickle = vec![b'('; 100_000];
+    let err = load(&pickle[..]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::LimitExceeded { limit: Limit::MetastackDepth, .. }));
+}
+
+#[test]
+fn deep_nesting_is_an_error() {
+    const LEVELS: usize = 100_000;
+
+    let lists = [&b"\x80\x02"[..], &b"]".repeat(LEVELS), &b"a".repeat(LEVELS - 1), b"."].concat();
+    let dicts = [&b"\x80\x02"[..], &b"}K\x00".repeat(LEVELS), b"N", &b"s".repeat(LEVELS), b"."].concat();
+    let tuples = [&b"\x80\x02N"[..], &b"\x85".repeat(LEVELS), b"."].concat();
+    let builds = [&b"\x80\x02N"[..], &b"Nb".repeat(LEVELS), b"."].concat();
+
+    for pickle in [lists, dicts, tuples, builds] {
+        let err = load(&pickle[..]).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::LimitExceeded { limit: Limit::NestingDepth, .. }), "{err}");
+    }
+}
+
+#[test]
+fn memo_copies_exceed_limits() {
+    // Each level is a list holding ten copies of the previous level, so the
+    // fully copied value grows tenfold per level from a few bytes of input.
+    let mut pickle = b"]q\x00".to_vec();
+    for level in 1..12u8 {
+        pickle.extend_from_slice(b"](");
+        for _ in 0..10 {
+            pickle.extend_from_slice(&[b'h', level - 1]);
+        }
+        pickle.extend_from_slice(&[b'e', b'q', level]);
+    }
+    pickle.push(b'.');
+
+    let limits = InterpreterLimits {
+        max_alloc_bytes: 1 << 20,
+        ..InterpreterLimits::default()
+    };
+    let mut interp = Interpreter::with_limits(limits);
+    let err = PickleReader::new(&pickle[..])
+        .map(|op| interp.exec_op(op?))
+        .find_map(Result::err)
+        .unwrap();
+    assert!(matches!(err.kind(), ErrorKind::LimitExceeded { limit: Limit::AllocBytes, .. }), "{err}");
+}
+
+#[test]
+fn nesting_exceeds_limits() {
+    let limits = InterpreterLimits {
+        max_nesting_depth: 2,
+        ..InterpreterLimits::default()
+    };
+
+    let ok = dilligent::load_with_limits(&b"]]]aa."[..], Default::default(), limits.clone()).unwrap();
+    assert!(ok.is_some());
+
+    for pickle in [&b"]]]]aaa."[..], b"]]]K\x01aaa.", b"(((K\x01\x85tt.", b"}K\x01}K\x01}K\x01K\x02sss.", b"NNbNbNb.", b"NNRNRNR."] {
+        let err = dilligent::load_with_limits(pickle, Default::default(), limits.clone()).unwrap_err();
+        assert!(
+            matches!(err.kind(), ErrorKind::LimitExceeded { limit: Limit::NestingDepth, .. }),
+            "{pickle:?}: {err}"
+        );
+    }
+}
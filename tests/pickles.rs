@@ -0,0 +1,172 @@
+use dilligent::{ErrorKind, Interpreter, PickleReader, Severity, Value};
+
+/// `pickle.dump(['a', 'a'], f, protocol=4)` followed by
+/// `pickle.dump(('b',), f, protocol=2)`.
+const TWO_PICKLES: &[u8] = b"\x80\x04\x95\x0b\x00\x00\x00\x00\x00\x00\x00]\x94(\x8c\x01a\x94h\x01e.\x80\x02X\x01\x00\x00\x00bq\x00\x85q\x01.";
+
+#[test]
+fn loads_each_pickle_in_turn() {
+    let values: Vec<Value> = dilligent::load_all(TWO_PICKLES).collect::<Result<_, _>>().unwrap();
+
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[0].as_list().map(|items| items.len()), Some(2));
+    assert_eq!(values[1].as_tuple().and_then(|items| items[0].as_str()), Some("b"));
+
+    assert_eq!(dilligent::load_all(&b""[..]).count(), 0);
+}
+
+#[test]
+fn memo_does_not_carry_over() {
+    // The second pickle gets memo entry 0 the first one put.
+    let mut pickles = dilligent::load_all(&b"\x80\x02X\x01\x00\x00\x00xq\x00.h\x00."[..]);
+
+    assert!(pickles.next().unwrap().is_ok());
+    let err = pickles.next().unwrap().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::MemoMiss(0)), "{err}");
+    assert!(pickles.next().is_none());
+}
+
+#[test]
+fn reports_trailing_data() {
+    let mut input = TWO_PICKLES.to_vec();
+    input.extend_from_slice(b"\xff\xfejunk");
+
+    let results: Vec<_> = dilligent::load_all(&input[..]).collect();
+    assert_eq!(results.len(), 3);
+
+    let err = results[2].as_ref().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::TrailingData(_)), "{err}");
+    assert_eq!(err.offset(), Some(TWO_PICKLES.len() as u64));
+
+    // So is a first op cut short after the first pickle.
+    let first_len = TWO_PICKLES.windows(3).position(|w| w == b".\x80\x02").unwrap() + 1;
+    let err = dilligent::load_all(&input[..first_len + 1]).nth(1).unwrap().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::TrailingData(_)), "{err}");
+    assert_eq!(err.offset(), Some(first_len as u64));
+}
+
+#[test]
+fn later_pickles_broken_further_in_keep_their_error() {
+    let err = dilligent::load_all(&TWO_PICKLES[..TWO_PICKLES.len() - 3]).nth(1).unwrap().unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Truncated), "{err}");
+
+    let reports = dilligent::Scanner::new().scan_all(&TWO_PICKLES[..TWO_PICKLES.len() - 3]);
+    assert!(matches!(reports[1].error.as_ref().map(|err| err.kind()), Some(ErrorKind::Truncated)));
+}
+
+#[test]
+fn scans_every_pickle() {
+    let mut input = TWO_PICKLES.to_vec();
+    input.extend_from_slice(b"cos\nsystem\n(S'id'\ntR.");
+
+    let reports = dilligent::Scanner::new().scan_all(&input[..]);
+    assert_eq!(reports.len(), 3);
+    assert!(reports.iter().all(|report| report.error.is_none()));
+    assert_eq!(reports[2].severity(), Severity::Malicious);
+    assert_eq!(reports[2].findings[0].offset, TWO_PICKLES.len() as u64);
+
+    input.push(0xff);
+    let reports = dilligent::Scanner::new().scan_all(&input[..]);
+    assert_eq!(reports.len(), 4);
+    assert!(matches!(reports[3].error.as_ref().map(|err| err.kind()), Some(ErrorKind::TrailingData(_))));
+}
+
+#[test]
+fn builds_builtin_types_written_as_calls() {
+    // `[{1}, frozenset({2}), bytearray(b'q\xff'), b'x\xff', b'']` at protocols 0 and 2.
+    let pickles: [&[u8]; 2] = [
+        b"(lp0\nc__builtin__\nset\np1\n((lp2\nI1\natp3\nRp4\nac__builtin__\nfrozenset\np5\n((lp6\nI2\natp7\nRp8\nac__builtin__\nbytearray\np9\n(c_codecs\nencode\np10\n(Vq\xff\np11\nVlatin1\np12\ntp13\nRp14\ntp15\nRp16\nag10\n(Vx\xff\np17\ng12\ntp18\nRp19\nac__builtin__\nbytes\np20\n(tRp21\na.",
+        b"\x80\x02]q\x00(c__builtin__\nset\nq\x01]q\x02K\x01a\x85q\x03Rq\x04c__builtin__\nfrozenset\nq\x05]q\x06K\x02a\x85q\x07Rq\x08c__builtin__\nbytearray\nq\tc_codecs\nencode\nq\nX\x03\x00\x00\x00q\xc3\xbfq\x0bX\x06\x00\x00\x00latin1q\x0c\x86q\rRq\x0e\x85q\x0fRq\x10h\nX\x03\x00\x00\x00x\xc3\xbfq\x11h\x0c\x86q\x12Rq\x13c__builtin__\nbytes\nq\x14)Rq\x15e.",
+    ];
+
+    for pickle in pickles {
+        let value = dilligent::load(pickle).unwrap().unwrap();
+        let items = value.as_list().unwrap();
+
+        assert!(matches!(&items[0], Value::Set(items) if items.iter().map(Value::as_i64).eq([Some(1)])), "{items:?}");
+        assert!(matches!(&items[1], Value::FrozenSet(items) if items.iter().map(Value::as_i64).eq([Some(2)])), "{items:?}");
+        assert!(matches!(&items[2], Value::ByteArray(data) if data == b"q\xff"), "{items:?}");
+        assert!(matches!(&items[3], Value::Bytes(data) if data == b"x\xff"), "{items:?}");
+        assert!(matches!(&items[4], Value::Bytes(data) if data.is_empty()), "{items:?}");
+    }
+}
+
+#[test]
+fn builds_newobj_ex_as_a_reduce() {
+    // `m.C` pickled at protocol 4 with `__getnewargs_ex__` returning
+    // `((1,), {'x': 2})`, and then `((1,), {})`.
+    let value = dilligent::load(&b"\x80\x04\x8c\x01m\x8c\x01C\x93K\x01\x85}\x8c\x01xK\x02s\x92."[..]).unwrap().unwrap();
+    let Value::Reduce(func, args) = value
+    else {
+        panic!("{value:?}")
+    };
+    assert!(matches!(&*func, Value::Global(global) if global.to_string() == "copyreg.__newobj_ex__"), "{func:?}");
+    let args = args.as_tuple().unwrap();
+    assert!(matches!(&args[0], Value::Global(global) if global.to_string() == "m.C"), "{args:?}");
+    assert_eq!(args[1].as_tuple().map(|items| items[0].as_i64()), Some(Some(1)));
+    assert_eq!(args[2].as_dict().and_then(|dict| dict.get("x")).and_then(Value::as_i64), Some(2));
+
+    let value = dilligent::load(&b"\x80\x04\x8c\x01m\x8c\x01C\x93K\x01\x85}\x92."[..]).unwrap().unwrap();
+    assert!(
+        matches!(&value, Value::Reduce(func, args) if matches!(&**func, Value::Global(global) if global.to_string() == "m.C") && args.as_tuple().map(|items| items.len()) == Some(1)),
+        "{value:?}"
+    );
+
+    let err = dilligent::load(&b"\x80\x04\x8c\x01m\x8c\x01C\x93K\x01\x85N\x92."[..]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::TypeMismatch(_)), "{err}");
+}
+
+#[test]
+fn takes_out_of_band_buffers() {
+    // `[PickleBuffer(b'xy'), PickleBuffer(bytearray(b'ab'))]` at protocol 5.
+    const PICKLE: &[u8] = b"\x80\x05](\x97\x98\x97e.";
+
+    let run = |buffers: Option<Vec<Vec<u8>>>| {
+        let mut interp = Interpreter::new();
+        if let Some(buffers) = buffers {
+            interp.set_buffers(buffers);
+        }
+        PickleReader::new(PICKLE).try_for_each(|op| interp.exec_op(op?).map(|_| ()))?;
+        Ok::<_, dilligent::Error>(interp.take_stop_value().unwrap())
+    };
+
+    let value = run(Some(vec![b"xy".to_vec(), b"ab".to_vec()])).unwrap();
+    let items = value.as_list().unwrap();
+    assert!(matches!(&items[0], Value::Bytes(data) if data == b"xy"), "{items:?}");
+    assert!(matches!(&items[1], Value::ByteArray(data) if data == b"ab"), "{items:?}");
+
+    for buffers in [None, Some(vec![b"xy".to_vec()])] {
+        let err = run(buffers).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)), "{err}");
+    }
+    let err = dilligent::load(PICKLE).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)), "{err}");
+}
+
+#[test]
+fn detects_pickles() {
+    let value = Value::List(vec![Value::String("x".to_string()), Value::Int(1)]);
+    for protocol in 2..=5 {
+        let mut pickle = Vec::new();
+        dilligent::dump(&value, &mut pickle, protocol).unwrap();
+        assert!(dilligent::is_pickle(&pickle), "protocol {protocol}");
+    }
+
+    // Protocol 0 and 1, and a prefix of one cut inside an op.
+    for pickle in [&b"(lp0\nVx\np1\naI1\na."[..], b"]q\x00(X\x01\x00\x00\x00xq\x01K\x01e.", b"(lp0\nVx"] {
+        assert!(dilligent::is_pickle(pickle), "{pickle:?}");
+    }
+
+    for not_pickle in [
+        &b""[..],
+        b"\x80",
+        b"\x80\x09]q\x00.",
+        b"hello world\n",
+        b"import os\nos.system('id')\n",
+        b"# README\n\nSome text.\n",
+        b"{\"a\": 1}\n",
+        b"\x7fELF\x02\x01\x01\x00",
+    ] {
+        assert!(!dilligent::is_pickle(not_pickle), "{not_pickle:?}");
+    }
+}
@@ -0,0 +1,61 @@
+use dilligent::{
+    Classification, ErrorKind, Global, GlobalPattern, GlobalPolicy, Interpreter, PickleReader, PolicyMode,
+};
+
+/// `os.system('echo hi')` as pickled by protocol 2.
+const OS_SYSTEM: &[u8] = b"\x80\x02cposix\nsystem\nq\x00X\x07\x00\x00\x00echo hiq\x01\x85q\x02Rq\x03.";
+
+fn run(policy: GlobalPolicy, pickle: &[u8]) -> (dilligent::Result<()>, Vec<Global>) {
+    let mut interp = Interpreter::new();
+    interp.set_policy(policy);
+
+    let result = PickleReader::new(pickle).try_for_each(|op| interp.exec_op(op?).map(|_| ()));
+    (result, interp.violations().to_vec())
+}
+
+#[test]
+fn patterns_match_dotted_paths() {
+    let global = Global::new("torch._utils", "_rebuild_tensor_v2");
+
+    assert!(GlobalPattern::from("torch._utils._rebuild_tensor_v2").matches(&global));
+    assert!(GlobalPattern::from("torch.*").matches(&global));
+    assert!(GlobalPattern::from("torch._utils.*").matches(&global));
+    assert!(GlobalPattern::from("*._rebuild_*").matches(&global));
+    assert!(!GlobalPattern::from("torchvision.*").matches(&global));
+    assert!(!GlobalPattern::from("torch._utils._rebuild_tensor").matches(&global));
+    assert!(GlobalPattern::from("torch.*Storage").matches(&Global::new("torch", "HalfStorage")));
+}
+
+#[test]
+fn deny_wins_over_allow() {
+    let policy = GlobalPolicy::new().allow("os.*").deny("os.system");
+
+    assert_eq!(policy.classify(&Global::new("os", "system")), Classification::Denied);
+    assert_eq!(policy.classify(&Global::new("os", "getcwd")), Classification::Allowed);
+    assert_eq!(policy.classify(&Global::new("sys", "exit")), Classification::Unknown);
+}
+
+#[test]
+fn abort_mode_stops_the_load() {
+    let policy = GlobalPolicy::new().deny("posix.*").mode(PolicyMode::Abort);
+    let (result, violations) = run(policy, OS_SYSTEM);
+
+    let err = result.unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::PolicyViolation(g) if g.name() == "system"));
+    assert!(violations.is_empty());
+}
+
+#[test]
+fn record_mode_keeps_loading() {
+    let policy = GlobalPolicy::new().deny("posix.*").mode(PolicyMode::Record);
+    let (result, violations) = run(policy, OS_SYSTEM);
+
+    result.unwrap();
+    assert_eq!(violations, vec![Global::new("posix", "system")]);
+}
+
+#[test]
+fn torch_policy_rejects_unknown_globals() {
+    let (result, _) = run(GlobalPolicy::torch_state_dict(), OS_SYSTEM);
+    assert!(matches!(result.unwrap_err().kind(), ErrorKind::PolicyViolation(_)));
+}
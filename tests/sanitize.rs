@@ -0,0 +1,187 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+use dilligent::{ErrorKind, Global, GlobalPolicy, LegacyCheckpoint, PolicyMode, Value};
+
+mod common;
+use common::STATE_DICT;
+
+/// `[1, os.system('id'), {'f': os.system}]` at protocol 0.
+const EVIL: &[u8] = b"(lp0\nI1\nacposix\nsystem\np1\n(Vid\np2\ntp3\nRp4\na(dp5\nVf\np6\ng1\nsa.";
+
+/// A legacy checkpoint of `{'bias': tensor([2., 3.])}` viewing a 3 float
+/// storage, with a trailing `'evil': os.system('id')` entry.
+const LEGACY_EVIL: &[&[u8]] = &[
+    b"\x80\x02\x8a\nl\xfc\x9cF\xf9 j\xa8P\x19.\x80\x02M\xe9\x03.\x80\x02}q\x00(X\x10\x00\x00\x00protocol_versionq\x01M\xe9\x03X\x0d\x00\x00\x00little_endianq\x02\x89u.",
+    b"\x80\x02}q\x00X\x04\x00\x00\x00biasq\x01ctorch._utils\n_rebuild_tensor_v2\nq\x02((X\x07\x00\x00\x00storageq\x03ctorch\nFloatStorage\nq\x04X\x01\x00\x00\x009q\x05X\x03\x00\x00\x00cpuq\x06K\x03Ntq\x07QK\x01K\x02\x85q\x08K\x01\x85q\x09\x89ccollections\nOrderedDict\nq\n)Rq\x0btq\x0cRq\x0dsX\x04\x00\x00\x00evilcposix\nsystem\nX\x02\x00\x00\x00id\x85Rs.",
+    b"\x80\x02]q\x00X\x01\x00\x00\x009q\x01a.\x00\x00\x00\x00\x00\x00\x00\x03?\x80\x00\x00@\x00\x00\x00@@\x00\x00",
+];
+
+/// A path in the temp dir unique to this test run.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dilligent-sanitize-{}-{}", std::process::id(), name))
+}
+
+fn run_sanitize(input: &[u8], name: &str) -> Vec<u8> {
+    let (model_file, output_file) = (temp_path(name), temp_path(&format!("clean-{}", name)));
+    fs::write(&model_file, input).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_dilligent"))
+        .arg("sanitize")
+        .arg(&model_file)
+        .arg(&output_file)
+        .output()
+        .unwrap();
+    let sanitized = fs::read(&output_file);
+    let _ = fs::remove_file(&model_file);
+    let _ = fs::remove_file(&output_file);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("replaced posix.system with None"));
+    sanitized.unwrap()
+}
+
+#[test]
+fn sanitizes_zip_checkpoints() {
+    // `[state_dict, os.system('id')]`, without STATE_DICT's protocol header.
+    let pickle = [b"\x80\x02]", &STATE_DICT[2..STATE_DICT.len() - 1], b"acposix\nsystem\nX\x02\x00\x00\x00id\x85Ra."].concat();
+    let entries: [(&str, Vec<u8>); 4] = [
+        ("archive/data.pkl", pickle),
+        ("archive/data/0", (0..6).flat_map(|i| (i as f32).to_le_bytes()).collect()),
+        ("archive/data/1", (0..3).flat_map(|i| (i as f32).to_le_bytes()).collect()),
+        ("archive/version", b"3\n".to_vec()),
+    ];
+
+    let mut model = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in entries.iter() {
+        model.start_file(*name, zip::write::FileOptions::default()).unwrap();
+        model.write_all(data).unwrap();
+    }
+    let model = model.finish().unwrap().into_inner();
+
+    let mut sanitized = zip::ZipArchive::new(Cursor::new(run_sanitize(&model, "model.pt"))).unwrap();
+    assert_eq!(sanitized.len(), entries.len());
+
+    for (name, data) in entries.iter() {
+        let mut entry = sanitized.by_name(name).unwrap();
+        assert_eq!(entry.compression(), zip::CompressionMethod::Stored);
+        assert_eq!(entry.data_start() % 64, 0, "{name}");
+
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).unwrap();
+        if *name != "archive/data.pkl" {
+            assert_eq!(&contents, data, "{name}");
+            continue;
+        }
+
+        assert!(!contents.windows(6).any(|window| window == b"system"));
+        let value = dilligent::load(&contents[..]).unwrap().unwrap();
+        let items = value.as_list().unwrap();
+        assert!(items[1].is_none());
+
+        let expected = dilligent::load(STATE_DICT).unwrap().unwrap();
+        assert_eq!(
+            format!("{:?}", dilligent::named_tensors(&items[0])),
+            format!("{:?}", dilligent::named_tensors(&expected))
+        );
+    }
+}
+
+#[test]
+fn sanitizes_legacy_checkpoints() {
+    let model = LEGACY_EVIL.concat();
+    let sanitized = run_sanitize(&model, "legacy.pt");
+    assert!(!sanitized.windows(6).any(|window| window == b"system"));
+
+    let before = LegacyCheckpoint::read(&mut Cursor::new(&model)).unwrap();
+    let after = LegacyCheckpoint::read(&mut Cursor::new(&sanitized)).unwrap();
+    let entry = |checkpoint: &LegacyCheckpoint, key: &str| {
+        checkpoint.value.as_dict().and_then(|dict| dict.get(key)).cloned().unwrap()
+    };
+    assert!(entry(&after, "evil").is_none());
+    assert_eq!(format!("{:?}", entry(&after, "bias")), format!("{:?}", entry(&before, "bias")));
+
+    let storage = |data: &[u8], checkpoint: &LegacyCheckpoint| {
+        let range = checkpoint.storage_range("9").unwrap();
+        data[range.start as usize..range.end as usize].to_vec()
+    };
+    assert_eq!(storage(&sanitized, &after), storage(&model, &before));
+    assert!(matches!(entry(&after, "bias"), Value::Tensor(_)));
+}
+
+#[test]
+fn replaces_disallowed_globals() {
+    let mut value = dilligent::load(EVIL).unwrap().unwrap();
+
+    let policy = GlobalPolicy::torch_weights_only().mode(PolicyMode::Record);
+    let removed = dilligent::sanitize(&mut value, &policy).unwrap();
+    assert_eq!(removed, vec![Global::new("posix", "system"); 2]);
+
+    let items = value.as_list().unwrap();
+    assert_eq!(items[0].as_i64(), Some(1));
+    assert!(items[1].is_none());
+    assert!(format!("{:?}", items[2]).contains("None"));
+
+    let mut pickle = Vec::new();
+    dilligent::dump(&value, &mut pickle, 2).unwrap();
+    assert!(!pickle.windows(6).any(|window| window == b"system"));
+}
+
+#[test]
+fn aborts_on_first_violation() {
+    let mut value = dilligent::load(EVIL).unwrap().unwrap();
+
+    let err = dilligent::sanitize(&mut value, &GlobalPolicy::torch_weights_only()).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::PolicyViolation(_)), "{err}");
+}
+
+#[test]
+fn checks_globals_written_for_native_values() {
+    // `[frozenset([1]), set([2])]` at protocol 4.
+    let mut value = dilligent::load(&b"\x80\x04]((K\x01\x91\x8f(K\x02\x90e."[..]).unwrap().unwrap();
+
+    let policy = GlobalPolicy::torch_weights_only().mode(PolicyMode::Record);
+    let removed = dilligent::sanitize(&mut value, &policy).unwrap();
+    assert_eq!(removed, vec![Global::new("builtins", "frozenset")]);
+
+    let items = value.as_list().unwrap();
+    assert!(items[0].is_none());
+    assert!(matches!(items[1], Value::Set(_)));
+}
+
+#[test]
+fn weights_only_rejects_numpy() {
+    let policy = GlobalPolicy::torch_weights_only();
+    assert!(policy.is_violation(&Global::new("numpy.core.multiarray", "_reconstruct")));
+    assert!(policy.is_violation(&Global::new("numpy", "ndarray")));
+    assert!(!policy.is_violation(&Global::new("torch._utils", "_rebuild_tensor_v2")));
+    assert!(!policy.is_violation(&Global::new("torch", "BFloat16Storage")));
+}
+
+#[test]
+fn keeps_state_dicts() {
+    let mut value = dilligent::load(STATE_DICT).unwrap().unwrap();
+    let before = format!("{:?}", dilligent::named_tensors(&value));
+
+    assert!(dilligent::sanitize(&mut value, &GlobalPolicy::torch_weights_only()).unwrap().is_empty());
+    assert_eq!(format!("{:?}", dilligent::named_tensors(&value)), before);
+}
+
+#[test]
+fn keeps_parameters() {
+    let mut value = dilligent::load(STATE_DICT).unwrap().unwrap();
+    assert!(dilligent::sanitize(&mut value, &GlobalPolicy::torch_weights_only()).unwrap().is_empty());
+
+    let mut pickle = Vec::new();
+    dilligent::dump(&value, &mut pickle, 2).unwrap();
+    assert!(pickle.windows(18).any(|window| window == b"_rebuild_parameter"));
+
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    let state_dict = value.as_ordered_dict().unwrap();
+    let weight = state_dict.get("weight").and_then(Value::as_tensor).unwrap();
+    assert!(weight.parameter && weight.requires_grad);
+    let bias = state_dict.get("bias").and_then(Value::as_tensor).unwrap();
+    assert!(!bias.parameter && !bias.requires_grad);
+}
@@ -0,0 +1,246 @@
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use dilligent::{ErrorKind, FindingKind, MemberReport, OpCode, ScanReport, Scanner, Severity};
+
+/// `eval('1+1')` as pickled by protocol 4, referenced through STACK_GLOBAL.
+const EVAL: &[u8] = b"\x80\x04\x95\x1f\x00\x00\x00\x00\x00\x00\x00\x8c\x08builtins\x94\x8c\x04eval\x94\x93\x94\x8c\x031+1\x94\x85\x94R\x94.";
+
+/// `OrderedDict(a=1)` as pickled by protocol 2.
+const ORDERED_DICT: &[u8] = b"\x80\x02ccollections\nOrderedDict\nq\x00)Rq\x01X\x01\x00\x00\x00aq\x02K\x01s.";
+
+#[test]
+fn stack_global_call_is_malicious() {
+    let report = Scanner::new().scan(EVAL);
+
+    assert!(report.error.is_none());
+    assert_eq!(report.severity(), Severity::Malicious);
+
+    let kinds: Vec<_> = report.findings.iter().map(|f| (f.kind, f.opcode, f.global.to_string())).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (FindingKind::Reference, OpCode::StackGlobal, "builtins.eval".to_string()),
+            (FindingKind::Call, OpCode::Reduce, "builtins.eval".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn ordered_dict_is_safe() {
+    let report = Scanner::new().scan(ORDERED_DICT);
+
+    assert!(report.error.is_none());
+    assert_eq!(report.severity(), Severity::Safe);
+    assert_eq!(report.findings.len(), 2);
+    assert_eq!(report.findings[0].offset, 2);
+}
+
+#[test]
+fn unknown_globals_are_suspicious() {
+    let report = Scanner::new().scan(&b"\x80\x02cfoo\nbar\n)R."[..]);
+
+    assert_eq!(report.severity(), Severity::Suspicious);
+}
+
+#[test]
+fn references_after_an_interpreter_error_are_still_reported() {
+    // The leading POP underflows, but the GLOBAL after it is still found.
+    let report = Scanner::new().scan(&b"0cos\nsystem\n."[..]);
+
+    assert!(report.error.is_some());
+    assert_eq!(report.severity(), Severity::Malicious);
+}
+
+#[test]
+fn truncated_pickles_are_suspicious() {
+    let report = Scanner::new().scan(&ORDERED_DICT[..ORDERED_DICT.len() - 1]);
+
+    assert!(report.error.is_some());
+    assert_eq!(report.severity(), Severity::Suspicious);
+}
+
+fn member_reports() -> Vec<MemberReport> {
+    let scanner = Scanner::new();
+    vec![
+        MemberReport {
+            path: PathBuf::from("model.pt"),
+            member: Some("archive/data.pkl".to_string()),
+            report: scanner.scan(ORDERED_DICT),
+        },
+        MemberReport {
+            path: PathBuf::from("model.pt"),
+            member: Some("archive/extra.pkl".to_string()),
+            report: scanner.scan(EVAL),
+        },
+        MemberReport {
+            path: PathBuf::from("cut.pkl"),
+            member: None,
+            report: scanner.scan(&ORDERED_DICT[..ORDERED_DICT.len() - 1]),
+        },
+    ]
+}
+
+#[test]
+fn json_lines_golden() {
+    let lines: Vec<String> = member_reports()
+        .iter()
+        .flat_map(MemberReport::json_findings)
+        .map(|line| line.to_string())
+        .collect();
+
+    assert_eq!(
+        lines,
+        [
+            r#"{"global":"collections.OrderedDict","kind":"reference","member":"archive/data.pkl","offset":2,"opcode":"Global","path":"model.pt","severity":"safe"}"#,
+            r#"{"global":"collections.OrderedDict","kind":"call","member":"archive/data.pkl","offset":30,"opcode":"Reduce","path":"model.pt","severity":"safe"}"#,
+            r#"{"global":"builtins.eval","kind":"reference","member":"archive/extra.pkl","offset":29,"opcode":"StackGlobal","path":"model.pt","severity":"malicious"}"#,
+            r#"{"global":"builtins.eval","kind":"call","member":"archive/extra.pkl","offset":39,"opcode":"Reduce","path":"model.pt","severity":"malicious"}"#,
+            r#"{"global":"collections.OrderedDict","kind":"reference","member":null,"offset":2,"opcode":"Global","path":"cut.pkl","severity":"safe"}"#,
+            r#"{"global":"collections.OrderedDict","kind":"call","member":null,"offset":30,"opcode":"Reduce","path":"cut.pkl","severity":"safe"}"#,
+            r#"{"error":"unexpected end of input","kind":"error","member":null,"offset":44,"opcode":null,"path":"cut.pkl","severity":"suspicious"}"#,
+        ]
+    );
+}
+
+#[test]
+fn sarif_golden() {
+    let result = |level: &str, index: u64, uri: &str, offset: u64, text: &str, fingerprint: &str, rule: &str| {
+        let lines: Vec<_> = member_reports().iter().flat_map(MemberReport::json_findings).collect();
+        let properties = lines
+            .into_iter()
+            .find(|line| line["offset"] == offset && (line["member"] == uri || line["path"] == uri))
+            .unwrap();
+
+        json!({
+            "level": level,
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "index": index, "uri": uri },
+                    "region": { "byteOffset": offset },
+                },
+            }],
+            "message": { "text": text },
+            "partialFingerprints": { "pickleGlobal/v1": fingerprint },
+            "properties": properties,
+            "ruleId": rule,
+        })
+    };
+
+    let expected = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "artifacts": [
+                { "location": { "uri": "model.pt" } },
+                { "location": { "uri": "archive/data.pkl" }, "parentIndex": 0 },
+                { "location": { "uri": "archive/extra.pkl" }, "parentIndex": 0 },
+                { "location": { "uri": "cut.pkl" } },
+            ],
+            // The safe OrderedDict findings are left out.
+            "results": [
+                result(
+                    "error",
+                    2,
+                    "archive/extra.pkl",
+                    29,
+                    "malicious reference of builtins.eval",
+                    "archive/extra.pkl:reference:builtins.eval",
+                    "dangerous-global",
+                ),
+                result(
+                    "error",
+                    2,
+                    "archive/extra.pkl",
+                    39,
+                    "malicious call of builtins.eval",
+                    "archive/extra.pkl:call:builtins.eval",
+                    "dangerous-global",
+                ),
+                result(
+                    "warning",
+                    3,
+                    "cut.pkl",
+                    44,
+                    "cut.pkl could not be read: unexpected end of input",
+                    ":error:unexpected end of input",
+                    "unreadable-pickle",
+                ),
+            ],
+            "tool": {
+                "driver": {
+                    "name": "dilligent",
+                    "rules": [
+                        {
+                            "defaultConfiguration": { "level": "error" },
+                            "id": "dangerous-global",
+                            "shortDescription": { "text": "Pickle references a known-dangerous callable" },
+                        },
+                        {
+                            "defaultConfiguration": { "level": "warning" },
+                            "id": "unknown-global",
+                            "shortDescription": {
+                                "text": "Pickle references a callable on neither the safe nor the dangerous list"
+                            },
+                        },
+                        {
+                            "defaultConfiguration": { "level": "warning" },
+                            "id": "unreadable-pickle",
+                            "shortDescription": { "text": "Pickle could not be read to the end" },
+                        },
+                    ],
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            },
+        }],
+        "version": "2.1.0",
+    });
+
+    assert_eq!(dilligent::sarif_log(&member_reports()), expected);
+}
+
+#[test]
+fn sarif_regions_of_several_pickles() {
+    let scanner = Scanner::new();
+    let stream = [EVAL, b"\x80\x02cfoo\nbar\n)R."].concat();
+    let mut reports: Vec<MemberReport> = scanner
+        .scan_all(&stream[..])
+        .into_iter()
+        .enumerate()
+        .map(|(i, report)| MemberReport {
+            path: PathBuf::from("stream.pkl"),
+            member: Some(format!("pickle-{}", i)),
+            report,
+        })
+        .collect();
+    reports.push(MemberReport {
+        path: PathBuf::from("odd.pkl"),
+        member: None,
+        report: ScanReport {
+            findings: Vec::new(),
+            error: Some(ErrorKind::Unsupported("pickle protocol 9".to_string()).into()),
+        },
+    });
+
+    let log = dilligent::sarif_log(&reports);
+    let locations: Vec<_> = log["runs"][0]["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| &result["locations"][0]["physicalLocation"])
+        .map(|location| (location["artifactLocation"]["uri"].as_str().unwrap(), location.get("region")))
+        .collect();
+
+    // Offsets in the second pickle count from the start of the stream.
+    let second = EVAL.len() as u64 + 2;
+    assert_eq!(
+        locations,
+        [
+            ("pickle-0", Some(&json!({ "byteOffset": 29 }))),
+            ("pickle-0", Some(&json!({ "byteOffset": 39 }))),
+            ("pickle-1", Some(&json!({ "byteOffset": second }))),
+            ("pickle-1", Some(&json!({ "byteOffset": second + 10 }))),
+            ("odd.pkl", None),
+        ]
+    );
+}
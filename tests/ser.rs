@@ -0,0 +1,69 @@
+use std::collections::{BTreeMap, HashMap};
+
+use dilligent::{ErrorKind, Op};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Kind {
+    Plain,
+    Scaled(f32),
+    Pair(u8, u8),
+    Named { a: i8 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Message {
+    id: u64,
+    name: String,
+    tags: Vec<String>,
+    pos: (i32, i32),
+    kinds: Vec<Kind>,
+    seed: Option<u64>,
+    big: u128,
+    counts: BTreeMap<String, i64>,
+}
+
+fn message() -> Message {
+    Message {
+        id: 7,
+        name: "run 'one'".to_string(),
+        tags: vec!["a".to_string(), "b".to_string()],
+        pos: (-1, 2),
+        kinds: vec![Kind::Plain, Kind::Scaled(0.5), Kind::Pair(1, 2), Kind::Named { a: -3 }],
+        seed: None,
+        big: u128::MAX,
+        counts: [("x".to_string(), -5)].into_iter().collect(),
+    }
+}
+
+#[test]
+fn round_trips_through_from_value() {
+    let pickle = dilligent::to_vec(&message()).unwrap();
+
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+    assert_eq!(dilligent::from_value::<Message>(&value).unwrap(), message());
+}
+
+#[test]
+fn writes_only_builtin_types() {
+    let pickle = dilligent::to_vec(&message()).unwrap();
+    assert_eq!(&pickle[..2], b"\x80\x04");
+
+    let ops: Vec<Op> = dilligent::PickleReader::new(&pickle[..]).collect::<Result<_, _>>().unwrap();
+    assert!(!ops.iter().any(|op| matches!(op, Op::Global(..) | Op::StackGlobal | Op::Reduce)));
+
+    // Python reads `{'a': (1, None)}` back from exactly this.
+    let value: HashMap<&str, (u8, ())> = [("a", (1, ()))].into_iter().collect();
+    assert_eq!(
+        dilligent::to_vec(&value).unwrap(),
+        b"\x80\x04\x95\x0b\x00\x00\x00\x00\x00\x00\x00}\x8c\x01a\x94K\x01N\x86s."
+    );
+}
+
+#[test]
+fn rejects_unhashable_keys() {
+    let value: BTreeMap<Vec<u8>, u8> = [(vec![1], 1)].into_iter().collect();
+
+    let err = dilligent::to_vec(&value).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Serialize(_)), "{err}");
+}
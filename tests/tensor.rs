@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Write};
+use std::process::Command;
+
+use dilligent::{DType, ErrorKind, Limit, SafetensorsTensor, Storage, Tensor, Value};
+
+mod common;
+use common::STATE_DICT;
+
+#[test]
+fn rebuilds_tensors_from_storages() {
+    let value = dilligent::load(STATE_DICT).unwrap().unwrap();
+    let state_dict = value.as_ordered_dict().unwrap();
+
+    let weight = state_dict.get("weight").and_then(Value::as_tensor).unwrap();
+    assert_eq!(weight.dtype(), DType::F32);
+    assert_eq!(weight.shape, vec![2, 3]);
+    assert_eq!(weight.strides, vec![3, 1]);
+    assert_eq!(weight.storage_offset, 0);
+    assert!(weight.requires_grad);
+    assert!(weight.parameter);
+    assert!(weight.is_contiguous());
+    assert_eq!(weight.storage.numel, 6);
+    assert_eq!(weight.storage.location, "cpu");
+    assert_eq!(weight.storage.data_entry("archive"), "archive/data/0");
+
+    let bias = state_dict.get("bias").and_then(Value::as_tensor).unwrap();
+    assert_eq!(bias.shape, vec![3]);
+    assert!(!bias.requires_grad);
+    assert!(!bias.parameter);
+    assert_eq!(bias.storage.data_entry("archive"), "archive/data/1");
+    assert_eq!(bias.storage.byte_len(), 12);
+}
+
+#[test]
+fn unknown_persistent_ids_stay_inert() {
+    // BINPERSID on ('other', 1) followed by a rebuild of it.
+    let pickle = b"\x80\x02ctorch._utils\n_rebuild_tensor_v2\n(X\x05\x00\x00\x00otherK\x01\x86QK\x00K\x01\x85K\x01\x85\x89)tR.";
+    let value = dilligent::load(&pickle[..]).unwrap().unwrap();
+
+    let Value::Reduce(func, _) = value else {
+        panic!("expected an inert reduce, got {:?}", value);
+    };
+    assert_eq!(func.as_global().unwrap().to_string(), "torch._utils._rebuild_tensor_v2");
+}
+
+#[test]
+fn named_tensors_are_listed_in_pickle_order() {
+    let value = dilligent::load(STATE_DICT).unwrap().unwrap();
+
+    let names: Vec<_> = dilligent::named_tensors(&value).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["weight", "bias"]);
+
+    // Wrapped as `{'model': state_dict}`, the key gains a prefix.
+    let mut nested = b"\x80\x02}X\x05\x00\x00\x00model".to_vec();
+    nested.extend_from_slice(&STATE_DICT[2..STATE_DICT.len() - 1]);
+    nested.extend_from_slice(b"s.");
+    let value = dilligent::load(&nested[..]).unwrap().unwrap();
+
+    let names: Vec<_> = dilligent::named_tensors(&value).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["model.weight", "model.bias"]);
+}
+
+fn float_tensor(numel: u64, storage_offset: u64, shape: &[u64], strides: &[u64]) -> Tensor {
+    Tensor {
+        storage: Storage {
+            dtype: DType::F32,
+            key: "0".to_string(),
+            location: "cpu".to_string(),
+            numel,
+            has_view_metadata: false,
+        },
+        storage_offset,
+        shape: shape.to_vec(),
+        strides: strides.to_vec(),
+        requires_grad: false,
+        parameter: false,
+    }
+}
+
+fn floats(values: &[f32], big_endian: bool) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|v| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() })
+        .collect()
+}
+
+#[test]
+fn contiguous_bytes_follow_offsets_and_strides() {
+    let storage = floats(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], false);
+
+    let row = float_tensor(6, 3, &[3], &[1]);
+    assert_eq!(row.contiguous_bytes(&storage, false).unwrap(), floats(&[4.0, 5.0, 6.0], false));
+
+    let transposed = float_tensor(6, 0, &[3, 2], &[1, 3]);
+    assert!(!transposed.is_contiguous());
+    assert_eq!(
+        transposed.contiguous_bytes(&storage, false).unwrap(),
+        floats(&[1.0, 4.0, 2.0, 5.0, 3.0, 6.0], false)
+    );
+
+    let big = floats(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], true);
+    assert_eq!(
+        transposed.contiguous_bytes(&big, true).unwrap(),
+        floats(&[1.0, 4.0, 2.0, 5.0, 3.0, 6.0], false)
+    );
+}
+
+#[test]
+fn contiguous_bytes_reject_data_outside_the_storage() {
+    let storage = floats(&[1.0, 2.0, 3.0], false);
+
+    assert!(float_tensor(3, 1, &[3], &[1]).contiguous_bytes(&storage, false).is_err());
+    assert!(float_tensor(3, 0, &[2, 2], &[u64::MAX, 1]).contiguous_bytes(&storage, false).is_err());
+    assert!(float_tensor(3, 0, &[0, 5], &[5, 1]).contiguous_bytes(&storage, false).unwrap().is_empty());
+}
+
+#[test]
+fn contiguous_bytes_cap_broadcast_tensors() {
+    let storage = floats(&[1.0], false);
+
+    // A zero stride repeats the single element, so this small broadcast is fine.
+    let small = float_tensor(1, 0, &[1000], &[0]);
+    assert_eq!(small.contiguous_bytes(&storage, false).unwrap(), floats(&[1.0; 1000], false));
+
+    let huge = float_tensor(1, 0, &[1 << 20, 1 << 20], &[0, 0]);
+    let err = huge.contiguous_bytes(&storage, false).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::LimitExceeded { limit: Limit::TensorBytes, .. }), "{err}");
+}
+
+#[test]
+fn safetensors_header_lists_offsets() {
+    let mut out = Vec::new();
+    let tensors = [
+        SafetensorsTensor { name: "weight", dtype: DType::F32, shape: &[2, 3] },
+        SafetensorsTensor { name: "steps", dtype: DType::I64, shape: &[3] },
+    ];
+    let metadata = BTreeMap::from([("format".to_string(), "pt".to_string())]);
+
+    dilligent::write_safetensors_header(&mut out, &tensors, &metadata).unwrap();
+
+    let len = u64::from_le_bytes(out[..8].try_into().unwrap()) as usize;
+    assert_eq!(len % 8, 0);
+    assert_eq!(out.len(), 8 + len);
+
+    let header = std::str::from_utf8(&out[8..]).unwrap().trim_end();
+    assert!(header.contains(r#""__metadata__":{"format":"pt"}"#));
+    assert!(header.contains(r#""weight":{"data_offsets":[0,24],"dtype":"F32","shape":[2,3]}"#));
+    assert!(header.contains(r#""steps":{"data_offsets":[24,48],"dtype":"I64","shape":[3]}"#));
+
+    let duplicate = [tensors[0].clone(), tensors[0].clone()];
+    assert!(dilligent::write_safetensors_header(&mut Vec::new(), &duplicate, &BTreeMap::new()).is_err());
+}
+
+#[test]
+fn npy_header_is_aligned_and_bfloat16_widens() {
+    let mut out = Vec::new();
+    dilligent::write_npy(&mut out, DType::BF16, &[2], &[0x80, 0x3f, 0x00, 0x40]).unwrap();
+
+    let len = u16::from_le_bytes([out[8], out[9]]) as usize;
+    assert_eq!(&out[..8], b"\x93NUMPY\x01\x00");
+    assert_eq!((10 + len) % 64, 0);
+
+    let header = std::str::from_utf8(&out[10..10 + len]).unwrap();
+    assert_eq!(header.trim_end(), "{'descr': '<f4', 'fortran_order': False, 'shape': (2,), }");
+    assert!(header.ends_with('\n'));
+    assert_eq!(&out[10 + len..], floats(&[1.0, 2.0], false));
+
+    assert!(dilligent::write_npy(&mut Vec::new(), DType::F32, &[2], &[0; 4]).is_err());
+}
+
+#[test]
+fn failed_conversions_leave_no_output() {
+    // The bias storage is missing, so converting fails after the weight is written.
+    let mut model = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    model.start_file("archive/data.pkl", Default::default()).unwrap();
+    model.write_all(STATE_DICT).unwrap();
+    model.start_file("archive/data/0", Default::default()).unwrap();
+    model.write_all(&floats(&[0.0; 6], false)).unwrap();
+    let model = model.finish().unwrap().into_inner();
+
+    let dir = std::env::temp_dir();
+    let model_file = dir.join(format!("dilligent-convert-{}.pt", std::process::id()));
+    std::fs::write(&model_file, model).unwrap();
+
+    for (args, output_file) in [
+        (&["convert", "--to", "safetensors"][..], format!("dilligent-convert-{}.safetensors", std::process::id())),
+        (&["extract"][..], format!("dilligent-convert-{}.npz", std::process::id())),
+    ] {
+        let output_file = dir.join(output_file);
+        let status = Command::new(env!("CARGO_BIN_EXE_dilligent"))
+            .args(args)
+            .arg(&model_file)
+            .arg(&output_file)
+            .output()
+            .unwrap()
+            .status;
+
+        assert!(!status.success(), "{args:?}");
+        assert!(!output_file.exists(), "{args:?}");
+    }
+
+    let _ = std::fs::remove_file(&model_file);
+}
@@ -0,0 +1,81 @@
+use dilligent::{PickleWriter, Value};
+
+mod common;
+use common::STATE_DICT;
+
+/// A pickle of most kinds of value, written by python at protocol 4.
+const MIXED: &[u8] = b"\x80\x04\x95\x81\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x04ints\x94(K\x00M\x00\x01J\xff\xff\xff\xff\x8a\x08\x00\x00\x00\x00\x00\x00\x00\x80\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00\x01t\x94\x8c\x05float\x94G?\xf8\x00\x00\x00\x00\x00\x00\x8c\x05bytes\x94C\x02\x00\xff\x94\x8c\x03set\x94\x8f\x94(K\x01K\x02\x90\x8c\x04none\x94N\x8c\x04list\x94]\x94(\x88\x89)\x8c\x01s\x94\x85\x94e\x8c\x06shared\x94h\x01u.";
+
+fn round_trip(value: &Value, protocol: u8) -> Value {
+    let mut pickle = Vec::new();
+    dilligent::dump(value, &mut pickle, protocol).unwrap();
+    dilligent::load(&pickle[..]).unwrap().unwrap()
+}
+
+#[test]
+fn round_trips_at_every_protocol() {
+    let value = dilligent::load(MIXED).unwrap().unwrap();
+
+    for protocol in 2..=5 {
+        assert_eq!(format!("{:?}", round_trip(&value, protocol)), format!("{:?}", value), "protocol {protocol}");
+    }
+}
+
+#[test]
+fn round_trips_tensors() {
+    let value = dilligent::load(STATE_DICT).unwrap().unwrap();
+
+    for protocol in 2..=5 {
+        let again = round_trip(&value, protocol);
+        assert_eq!(dilligent::named_tensors(&again), dilligent::named_tensors(&value), "protocol {protocol}");
+    }
+}
+
+#[test]
+fn memoizes_repeated_strings() {
+    let value = Value::List(vec![Value::String("shared".to_string()); 3]);
+
+    let mut pickle = Vec::new();
+    dilligent::dump(&value, &mut pickle, 2).unwrap();
+    assert_eq!(pickle, b"\x80\x02](X\x06\x00\x00\x00sharedq\x00h\x00h\x00e.");
+}
+
+#[test]
+fn frames_from_protocol_4() {
+    let big = Value::List((0..20_000).map(|i| Value::String(format!("item {i}"))).collect());
+
+    let mut writer = PickleWriter::new(Vec::new(), 4).unwrap();
+    writer.dump(&Value::None).unwrap();
+    writer.dump(&big).unwrap();
+    let pickle = writer.into_inner();
+
+    // Too small to frame, then the list in several frames.
+    assert_eq!(&pickle[..4], b"\x80\x04N.");
+    assert_eq!(&pickle[4..7], b"\x80\x04\x95");
+
+    let ops: Vec<_> = dilligent::PickleReader::new(&pickle[4..]).collect::<Result<_, _>>().unwrap();
+    assert!(ops.iter().filter(|op| matches!(op, dilligent::Op::Frame(_))).count() > 1);
+
+    let values: Vec<_> = dilligent::load_all(&pickle[..]).collect::<Result<_, _>>().unwrap();
+    assert_eq!(values[1].as_list().map(<[Value]>::len), Some(20_000));
+
+    assert!(PickleWriter::new(Vec::new(), 1).is_err());
+}
+
+#[test]
+fn writes_deeply_nested_values() {
+    let mut value = Value::None;
+    for _ in 0..3000 {
+        value = Value::List(vec![value]);
+    }
+
+    let mut pickle = Vec::new();
+    dilligent::dump(&value, &mut pickle, 4).unwrap();
+
+    // Too deep for the interpreter to load back.
+    let err = dilligent::load(&pickle[..]).unwrap_err();
+    assert!(
+        matches!(err.kind(), dilligent::ErrorKind::LimitExceeded { limit: dilligent::Limit::NestingDepth, .. }),
+        "{err}"
+    );
+}